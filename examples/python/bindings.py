@@ -1,8 +1,12 @@
 from concurrent.futures import ThreadPoolExecutor
-from ctypes import Structure, CDLL, POINTER, byref, string_at, c_uint8, c_char_p, c_size_t
+from ctypes import (
+    Structure, CDLL, CFUNCTYPE, POINTER, byref, string_at, c_uint8, c_uint64, c_char_p,
+    c_size_t, c_void_p,
+)
 from functools import partial
+from math import ceil, isfinite
 from os import path
-from typing import Tuple, Optional
+from typing import Callable, Tuple, Optional
 
 
 class _BfExecResult(Structure):
@@ -13,10 +17,86 @@ class _BfExecResult(Structure):
     ]
 
 
+class _BfExecOptions(Structure):
+    _fields_ = [
+        ("cell_size", c_uint8),
+        ("overflow", c_uint8),
+        ("tape_size", c_size_t),
+        ("tape_edge", c_uint8),
+        ("left_edge", c_uint8),
+        ("eof", c_uint8),
+        ("max_cells", c_size_t),
+    ]
+
+
+class _BfExecLimits(Structure):
+    _fields_ = [
+        ("max_steps", c_uint64),
+        ("timeout_ms", c_uint64),
+    ]
+
+    @classmethod
+    def new(cls, max_steps: Optional[int], timeout: Optional[float]) -> "_BfExecLimits":
+        """Limits in the units the library takes, rounding the timeout up to whole milliseconds as
+        a timeout of 0 means no timeout at all."""
+        timeout = timeout or 0
+        # ctypes silently truncates values that do not fit
+        if not (isfinite(timeout) and 0 <= ceil(timeout * 1000) <= 2**64 - 1):
+            raise ValueError("invalid timeout '%s'" % timeout)
+        return cls(max_steps or 0, ceil(timeout * 1000))
+
+
+class _BfExecLimitedResult(Structure):
+    _fields_ = [
+        ("result", _BfExecResult),
+        ("exhausted", c_uint8),
+        ("cancelled", c_uint8),
+        ("steps", c_uint64),
+    ]
+
+
+class _BfExecStreamResult(Structure):
+    _fields_ = [
+        ("success", c_uint8),
+        ("exhausted", c_uint8),
+        ("cancelled", c_uint8),
+        ("steps", c_uint64),
+    ]
+
+
+_BfOutputCallback = CFUNCTYPE(None, c_void_p, POINTER(c_uint8), c_size_t)
+
+
 class BfBindings(object):
     LIBNAME = "libbfi"
+    OVERFLOW_POLICIES = {"wrap": 0, "saturate": 1, "error": 2}
+    TAPE_EDGE_POLICIES = {"error": 0, "wrap": 1, "clamp": 2}
+    LEFT_EDGE_POLICIES = {"grow": 0, "error": 1}
+    EOF_POLICIES = {"unchanged": 0, "zero": 1, "all-ones": 2, "error": 3}
     FUNTYPES = {
         "bf_exec": ([c_char_p, POINTER(c_uint8), c_size_t], _BfExecResult),
+        "bf_exec_with_options": (
+            [c_char_p, POINTER(c_uint8), c_size_t, _BfExecOptions], _BfExecResult
+        ),
+        "bf_exec_limited": (
+            [c_char_p, POINTER(c_uint8), c_size_t, _BfExecOptions, _BfExecLimits],
+            _BfExecLimitedResult,
+        ),
+        "bf_exec_cancellable": (
+            [c_char_p, POINTER(c_uint8), c_size_t, _BfExecOptions, _BfExecLimits, c_void_p],
+            _BfExecLimitedResult,
+        ),
+        "bf_exec_streaming": (
+            [
+                c_char_p, POINTER(c_uint8), c_size_t, _BfExecOptions, _BfExecLimits, c_void_p,
+                _BfOutputCallback, c_void_p,
+            ],
+            _BfExecStreamResult,
+        ),
+        "bf_cancel_new": ([], c_void_p),
+        "bf_cancel": ([c_void_p], None),
+        "bf_cancel_free": ([c_void_p], None),
+        "bf_default_options": ([], _BfExecOptions),
         "bf_free": ([POINTER(c_uint8), c_size_t], None),
     }
 
@@ -37,22 +117,52 @@ class BfBindings(object):
             fun.argtypes = argtypes
             fun.restype = restype
 
-    def execute(self, program: bytes, program_input: Optional[bytes] = None) -> Tuple[bool, bytes]:
+    def execute(
+        self,
+        program: bytes,
+        program_input: Optional[bytes] = None,
+        cell_size: int = 8,
+        overflow: str = "wrap",
+        tape_size: Optional[int] = None,
+        tape_edge: str = "error",
+        left_edge: str = "grow",
+        eof: str = "unchanged",
+        max_steps: Optional[int] = None,
+        timeout: Optional[float] = None,
+        max_cells: Optional[int] = None,
+    ) -> Tuple[bool, bytes]:
         """Call to execute a program with an optional input byte buffer. Kind of funny that manually
         freeing the result is necessary for leak-free interop between Python and Rust, neither of
         which must be managed this way when used alone."""
         input_bytes = program_input or b""
         input_type = c_uint8 * len(input_bytes)
         inp = input_type.from_buffer(bytearray(input_bytes))
+        options = self.lib.bf_default_options()
+        options.cell_size = cell_size
+        options.overflow = self.OVERFLOW_POLICIES[overflow]
+        options.tape_size = tape_size or 0
+        options.tape_edge = self.TAPE_EDGE_POLICIES[tape_edge]
+        options.left_edge = self.LEFT_EDGE_POLICIES[left_edge]
+        options.eof = self.EOF_POLICIES[eof]
+        options.max_cells = max_cells or 0
+        limits = _BfExecLimits.new(max_steps, timeout)
+        cancel = self.lib.bf_cancel_new()
         # by default foreign calls release the GIL, meaning that the Ctrl-C is not processed if
-        # `lib.bf_exec` is runniing in the foreground
-        future = self._pool.submit(partial(self.lib.bf_exec, program, inp, len(input_bytes)))
+        # `lib.bf_exec_cancellable` is runniing in the foreground
+        future = self._pool.submit(partial(
+            self.lib.bf_exec_cancellable, program, inp, len(input_bytes), options, limits, cancel
+        ))
         try:
-            result = future.result()
+            result = future.result().result
         except KeyboardInterrupt:
-            # TODO: this doesn't actually stop the running job in the thread...
-            future.cancel() # ensure that the pool is freed of this process
+            # stop the program and wait for it to wind down before the handle is freed
+            self.lib.bf_cancel(cancel)
+            result = future.result().result
+            if result.success == 1:
+                self.lib.bf_free(result.output, result.output_length)
             raise
+        finally:
+            self.lib.bf_cancel_free(cancel)
         success = result.success == 1
         output = string_at(result.output, size=result.output_length) if success else b""
         # while the program won't crash if asked to free 0 bytes from an invalid location (which is
@@ -63,3 +173,25 @@ class BfBindings(object):
             # return the underlying pointer to Rust so the memory does not leak
             self.lib.bf_free(result.output, result.output_length)
         return success, output
+
+    def execute_streaming(
+        self,
+        program: bytes,
+        on_output: Callable[[bytes], None],
+        program_input: Optional[bytes] = None,
+        max_steps: Optional[int] = None,
+        timeout: Optional[float] = None,
+    ) -> bool:
+        """Execute a program with the default options, calling `on_output` with each piece of
+        output as soon as the program writes it rather than returning it all at the end."""
+        input_bytes = program_input or b""
+        input_type = c_uint8 * len(input_bytes)
+        inp = input_type.from_buffer(bytearray(input_bytes))
+        limits = _BfExecLimits.new(max_steps, timeout)
+        # keep a reference to the callback for as long as Rust may call it
+        callback = _BfOutputCallback(lambda _, output, length: on_output(string_at(output, length)))
+        result = self.lib.bf_exec_streaming(
+            program, inp, len(input_bytes), self.lib.bf_default_options(), limits, None, callback,
+            None,
+        )
+        return result.success == 1
@@ -2,7 +2,6 @@ extern crate bfi;
 extern crate zmq;
 
 use std::io::Write;
-use std::cell::RefCell;
 use std::io::{self, Error, ErrorKind};
 
 use bfi::{ioctx, interpreter};
@@ -111,7 +110,6 @@ fn main() {
     // reads two input bytes and returns their wrapped sum, nonterminating
     let adder: &str = "+[-,>,<[->+<]>.[-]<+]";
     let ictx = ZmqRepServerIoCtx::new("tcp", "127.0.0.1", 12345u32);
-    let ictx_rc = RefCell::new(Box::new(ictx) as Box<dyn ioctx::IoCtx>);
-    let status = interpreter::ExecutionContext::new(ictx_rc.borrow_mut(), adder).execute();
+    let status = interpreter::ExecutionContext::new(ictx, adder).execute();
     println!("exit status: {:?}", status);
 }
@@ -9,6 +9,6 @@ fn main() {
             let output_str: &str = std::str::from_utf8(output_vec.as_slice()).unwrap();
             println!("program output: {}", output_str);
         },
-        Err(e) => eprintln!("error: {:?}", e),
+        Err(e) => eprintln!("error: {}", e),
     };
 }
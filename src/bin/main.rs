@@ -1,59 +1,162 @@
+#[macro_use]
 extern crate clap;
 
-use std::cell::RefCell;
+use std::io;
+use std::time::Duration;
 
-use clap::{App, Arg, ArgMatches};
+use clap::{App, Arg, ArgMatches, SubCommand};
 
+use bfi::{Error, Interpreter};
+use bfi::config::{
+    CellWidth, Config, EdgePolicy, EofPolicy, LeftEdgePolicy, MemoryLimit, OverflowPolicy,
+};
 use bfi::ioctx::{IoCtx, StdIoCtx, UnbufferedStdIoCtx};
-use bfi::interpreter::{ExecutionStatus, ExecutionContext};
+use bfi::interpreter::ExecutionStatus;
 
 
 static PROGRAM_ARG: &str = "program";
 static VERBOSE_ARG: &str = "verbose";
 static FILE_ARG: &str = "file";
 static UNBUFFERED_FLAG: &str = "unbuffered";
+static CELL_SIZE_ARG: &str = "cell-size";
+static OVERFLOW_ARG: &str = "overflow";
+static TAPE_SIZE_ARG: &str = "tape-size";
+static TAPE_EDGE_ARG: &str = "tape-edge";
+static LEFT_EDGE_ARG: &str = "left-edge";
+static EOF_ARG: &str = "eof";
+static MAX_STEPS_ARG: &str = "max-steps";
+static TIMEOUT_ARG: &str = "timeout";
+static MAX_CELLS_ARG: &str = "max-cells";
+static MAX_BYTES_ARG: &str = "max-bytes";
+static CHECK_COMMAND: &str = "check";
+
+
+fn program_args(required: bool) -> Vec<Arg<'static, 'static>> {
+    let program = Arg::with_name(PROGRAM_ARG)
+        .help("Program to execute, or launch interactive session if no prorgram is provided")
+        .conflicts_with(FILE_ARG)
+        .index(1);
+    vec![
+        match required {
+            true => program.required_unless(FILE_ARG).help("Program to check"),
+            false => program,
+        },
+        Arg::with_name(FILE_ARG)
+            .short("f")
+            .long("file")
+            .takes_value(true)
+            .value_name("FILE")
+            .conflicts_with(PROGRAM_ARG)
+            .help("Program file to execute"),
+    ]
+}
 
 
 fn get_command_line_args() -> ArgMatches<'static> {
     App::new("bfi")
         .version("0.1")
         .about("BrainF*ck language interpreter")
-        .arg(Arg::with_name(PROGRAM_ARG)
-            .help("Program to execute, or launch interactive session if no prorgram is provided")
-            .conflicts_with(FILE_ARG)
-            .index(1))
+        .args(&program_args(false))
         .arg(Arg::with_name(VERBOSE_ARG)
             .short("v")
             .long("verbose")
             .help("Toggle high verbosity"))
-        .arg(Arg::with_name(FILE_ARG)
-            .short("f")
-            .long("file")
-            .takes_value(true)
-            .value_name("FILE")
-            .conflicts_with(PROGRAM_ARG)
-            .help("Program file to execute"))
         .arg(Arg::with_name(UNBUFFERED_FLAG)
             .long("unbuffered")
             .takes_value(false)
             .help("Do not buffer output (note: may break output character encoding)"))
+        .arg(Arg::with_name(CELL_SIZE_ARG)
+            .long("cell-size")
+            .takes_value(true)
+            .value_name("BITS")
+            .possible_values(&["8", "16", "32", "64"])
+            .default_value("8")
+            .help("Size of each cell on the tape, 64-bit cells are signed"))
+        .arg(Arg::with_name(OVERFLOW_ARG)
+            .long("overflow")
+            .takes_value(true)
+            .value_name("POLICY")
+            .possible_values(&["wrap", "saturate", "error"])
+            .default_value("wrap")
+            .help("What to do when a cell is taken past the largest or smallest value it holds"))
+        .arg(Arg::with_name(TAPE_SIZE_ARG)
+            .long("tape-size")
+            .takes_value(true)
+            .value_name("CELLS")
+            .validator(|s| match s.parse::<usize>() {
+                Ok(0) | Err(_) => Err(format!("invalid tape size '{}'", s)),
+                Ok(_) => Ok(()),
+            })
+            .help("Use a bounded tape with this many cells (e.g. 30000) instead of growing it"))
+        .arg(Arg::with_name(TAPE_EDGE_ARG)
+            .long("tape-edge")
+            .takes_value(true)
+            .value_name("POLICY")
+            .possible_values(&["error", "wrap", "clamp"])
+            .default_value("error")
+            .help("What to do when the pointer moves past either end of a bounded tape"))
+        .arg(Arg::with_name(LEFT_EDGE_ARG)
+            .long("left-edge")
+            .takes_value(true)
+            .value_name("POLICY")
+            .possible_values(&["grow", "error"])
+            .default_value("grow")
+            .help("What to do when the pointer moves left of the first cell of an unbounded tape"))
+        .arg(Arg::with_name(EOF_ARG)
+            .long("eof")
+            .takes_value(true)
+            .value_name("POLICY")
+            .possible_values(&["unchanged", "zero", "all-ones", "error"])
+            .default_value("unchanged")
+            .help("What to do to the current cell when input is requested and none is left"))
+        .arg(Arg::with_name(MAX_STEPS_ARG)
+            .long("max-steps")
+            .takes_value(true)
+            .value_name("STEPS")
+            .validator(|s| match s.parse::<u64>() {
+                Ok(_) => Ok(()),
+                Err(_) => Err(format!("invalid number of steps '{}'", s)),
+            })
+            .help("Stop the program after this many steps"))
+        .arg(Arg::with_name(TIMEOUT_ARG)
+            .long("timeout")
+            .takes_value(true)
+            .value_name("SECONDS")
+            .validator(|s| match parse_timeout(&s) {
+                Some(_) => Ok(()),
+                None => Err(format!("invalid timeout '{}'", s)),
+            })
+            .help("Stop the program after this many seconds"))
+        .arg(Arg::with_name(MAX_CELLS_ARG)
+            .long("max-cells")
+            .takes_value(true)
+            .value_name("CELLS")
+            .conflicts_with(MAX_BYTES_ARG)
+            .validator(|s| match s.parse::<usize>() {
+                Ok(0) | Err(_) => Err(format!("invalid number of cells '{}'", s)),
+                Ok(_) => Ok(()),
+            })
+            .help("Stop the program if the tape grows past this many cells"))
+        .arg(Arg::with_name(MAX_BYTES_ARG)
+            .long("max-bytes")
+            .takes_value(true)
+            .value_name("BYTES")
+            .conflicts_with(MAX_CELLS_ARG)
+            .validator(|s| match s.parse::<usize>() {
+                Ok(n) if n >= 8 => Ok(()),
+                _ => Err(format!("invalid number of bytes '{}', each cell takes 8", s)),
+            })
+            .help("Stop the program if the tape grows past this many bytes of memory"))
+        .subcommand(SubCommand::with_name(CHECK_COMMAND)
+            .about("Check a program for errors without executing it")
+            // there is no interactive session to fall back to without a program
+            .args(&program_args(true)))
         .get_matches()
 }
 
 
-fn get_io_context(unbuffered: bool) -> Box<dyn IoCtx> {
-    if unbuffered {
-        Box::new(UnbufferedStdIoCtx::default())
-    } else {
-        Box::new(StdIoCtx::default())
-    }
-}
-
-
-fn main() {
-    let opts = get_command_line_args();
-
-    let program_string: String = match (opts.value_of(PROGRAM_ARG), opts.value_of(FILE_ARG)) {
+fn get_program_string(opts: &ArgMatches) -> String {
+    match (opts.value_of(PROGRAM_ARG), opts.value_of(FILE_ARG)) {
         (Some(s), None) => s.to_string(),
         (None, Some(filename)) => match std::fs::read_to_string(filename) {
             Ok(contents) => contents,
@@ -66,15 +169,81 @@ fn main() {
         (None, None) => "!".to_string(),
         // final arm should never be reached due to mutual `conflicts_with`
         _ => unreachable!(),
+    }
+}
+
+
+/// Number of seconds as a `Duration`, if it is one that can be represented.
+fn parse_timeout(s: &str) -> Option<Duration> {
+    s.parse::<f64>().ok().and_then(|t| Duration::try_from_secs_f64(t).ok())
+}
+
+
+fn get_config(opts: &ArgMatches) -> Config {
+    Config {
+        // parsing cannot fail thanks to `possible_values`
+        cell_width: value_t!(opts, CELL_SIZE_ARG, CellWidth).unwrap_or_default(),
+        overflow: value_t!(opts, OVERFLOW_ARG, OverflowPolicy).unwrap_or_default(),
+        tape_size: value_t!(opts, TAPE_SIZE_ARG, usize).ok(),
+        tape_edge: value_t!(opts, TAPE_EDGE_ARG, EdgePolicy).unwrap_or_default(),
+        left_edge: value_t!(opts, LEFT_EDGE_ARG, LeftEdgePolicy).unwrap_or_default(),
+        eof: value_t!(opts, EOF_ARG, EofPolicy).unwrap_or_default(),
+        max_steps: value_t!(opts, MAX_STEPS_ARG, u64).ok(),
+        timeout: opts.value_of(TIMEOUT_ARG).and_then(parse_timeout),
+        memory_limit: match (opts.value_of(MAX_CELLS_ARG), opts.value_of(MAX_BYTES_ARG)) {
+            (Some(_), _) => value_t!(opts, MAX_CELLS_ARG, usize).ok().map(MemoryLimit::Cells),
+            (_, Some(_)) => value_t!(opts, MAX_BYTES_ARG, usize).ok().map(MemoryLimit::Bytes),
+            (None, None) => None,
+        },
+    }
+}
+
+
+fn check(program_string: &str) -> i32 {
+    match bfi::check(program_string) {
+        Ok(()) => 0,
+        Err(diagnostics) => {
+            for diagnostic in diagnostics {
+                eprintln!("bfi: {}", diagnostic);
+            }
+            1
+        },
+    }
+}
+
+
+fn get_io_context(unbuffered: bool) -> Box<dyn IoCtx> {
+    if unbuffered {
+        Box::new(UnbufferedStdIoCtx::default())
+    } else {
+        Box::new(StdIoCtx::default())
+    }
+}
+
+
+fn main() {
+    let opts = get_command_line_args();
+
+    if let Some(check_opts) = opts.subcommand_matches(CHECK_COMMAND) {
+        std::process::exit(check(get_program_string(check_opts).as_str()));
     };
 
+    let program_string: String = get_program_string(&opts);
+
     // Creating the io_context inside a block like this ensures that it is dropped before the call
     // to std::process::exit, necessary to flush output buffer for stdout
     let retcode: i32 = {
-        let io_context = RefCell::new(get_io_context(opts.is_present(UNBUFFERED_FLAG)));
+        let io_context = get_io_context(opts.is_present(UNBUFFERED_FLAG));
 
-        let execution_status: ExecutionStatus<String> =
-            ExecutionContext::new(io_context.borrow_mut(), program_string.as_str()).execute();
+        let execution_status: ExecutionStatus = match Interpreter::builder()
+            .program(program_string.as_str())
+            .io(io_context)
+            .config(get_config(&opts))
+            .build()
+        {
+            Ok(mut ectx) => ectx.execute(),
+            Err(err) => ExecutionStatus::Stopped(err),
+        };
 
         match execution_status {
             ExecutionStatus::Terminated => {
@@ -83,7 +252,9 @@ fn main() {
                 };
                 0
             },
-            ExecutionStatus::ProgramError(err) | ExecutionStatus::InternalError(err) => {
+            // the reader has gone away (e.g. `bfi program | head -1`), which is not worth a word
+            ExecutionStatus::Stopped(Error::Io { kind: io::ErrorKind::BrokenPipe, .. }) => 0,
+            ExecutionStatus::Stopped(err) => {
                 eprintln!("bfi: exited with error: {}", err);
                 1
             },
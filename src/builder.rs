@@ -0,0 +1,245 @@
+//! Builder assembling an `ExecutionContext` from a program, an I/O context and options.
+
+use std::time::Duration;
+
+use crate::cancel::CancellationToken;
+use crate::config::{
+    CellWidth, Config, EdgePolicy, EofPolicy, LeftEdgePolicy, MemoryLimit, OverflowPolicy,
+};
+use crate::error::Error;
+use crate::interpreter::{ExecutionContext, ExecutionStatus};
+use crate::ioctx::{InMemoryIoCtx, IoCtx};
+use crate::observer::{ExecutionObserver, NoObserver};
+
+
+/// Entry point to the builder, see `Interpreter::builder`.
+pub struct Interpreter;
+
+
+impl Interpreter {
+    /// Start assembling an execution. Everything is optional: the program defaults to the empty
+    /// program, the I/O context to an `InMemoryIoCtx` and the options to `Config::default()`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// extern crate bfi;
+    ///
+    /// use bfi::Interpreter;
+    /// use bfi::config::CellWidth;
+    /// use bfi::interpreter::ExecutionStatus;
+    ///
+    /// fn main () {
+    ///     let mut ectx = Interpreter::builder()
+    ///         .program("-")
+    ///         .cell_width(CellWidth::U16)
+    ///         .max_steps(100)
+    ///         .build()
+    ///         .unwrap();
+    ///     assert_eq!(ectx.execute(), ExecutionStatus::Terminated);
+    ///     assert_eq!(ectx.tape().to_vec(), vec![65535]);
+    ///     // options that do not make sense are caught before anything runs
+    ///     assert!(Interpreter::builder().program("[").build().is_err());
+    ///     assert!(Interpreter::builder().tape_size(0).build().is_err());
+    /// }
+    /// ```
+    pub fn builder() -> InterpreterBuilder<InMemoryIoCtx> {
+        InterpreterBuilder::default()
+    }
+}
+
+
+/// Collects the program, I/O context, observer and options of an execution until `build` is
+/// called.
+pub struct InterpreterBuilder<I: IoCtx, O: ExecutionObserver = NoObserver> {
+    program: Result<String, Error>,
+    io: I,
+    observer: O,
+    config: Config,
+    cancellation: Option<CancellationToken>,
+}
+
+
+impl Default for InterpreterBuilder<InMemoryIoCtx> {
+    fn default() -> Self {
+        InterpreterBuilder {
+            program: Ok(String::new()),
+            io: InMemoryIoCtx::default(),
+            observer: NoObserver,
+            config: Config::default(),
+            cancellation: None,
+        }
+    }
+}
+
+
+impl<I: IoCtx, O: ExecutionObserver> InterpreterBuilder<I, O> {
+    /// Source of the program to execute.
+    pub fn program(mut self, program: &str) -> Self {
+        self.program = Ok(program.to_string());
+        self
+    }
+
+    /// Source of the program to execute as raw bytes, which `build` rejects with
+    /// `Error::InvalidEncoding` unless they are valid UTF-8.
+    pub fn program_bytes(mut self, program: &[u8]) -> Self {
+        self.program = String::from_utf8(program.to_vec()).map_err(|_| Error::InvalidEncoding);
+        self
+    }
+
+    /// I/O context the program reads input from and writes output to, replacing the current one.
+    pub fn io<J: IoCtx>(self, io: J) -> InterpreterBuilder<J, O> {
+        InterpreterBuilder {
+            program: self.program,
+            io,
+            observer: self.observer,
+            config: self.config,
+            cancellation: self.cancellation,
+        }
+    }
+
+    /// Observer to report execution to, replacing the current one, see
+    /// `ExecutionContext::with_observer`.
+    pub fn observer<P: ExecutionObserver>(self, observer: P) -> InterpreterBuilder<I, P> {
+        InterpreterBuilder {
+            program: self.program,
+            io: self.io,
+            observer,
+            config: self.config,
+            cancellation: self.cancellation,
+        }
+    }
+
+    /// All of the options at once, replacing any set so far.
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Width of each cell, see `Config::cell_width`.
+    pub fn cell_width(mut self, cell_width: CellWidth) -> Self {
+        self.config.cell_width = cell_width;
+        self
+    }
+
+    /// What to do when a cell overflows, see `Config::overflow`.
+    pub fn overflow(mut self, overflow: OverflowPolicy) -> Self {
+        self.config.overflow = overflow;
+        self
+    }
+
+    /// Use a bounded tape with this many cells, see `Config::tape_size`.
+    pub fn tape_size(mut self, tape_size: usize) -> Self {
+        self.config.tape_size = Some(tape_size);
+        self
+    }
+
+    /// Behavior at both ends of a bounded tape, see `Config::tape_edge`.
+    pub fn tape_edge(mut self, tape_edge: EdgePolicy) -> Self {
+        self.config.tape_edge = tape_edge;
+        self
+    }
+
+    /// Behavior left of the starting cell of an unbounded tape, see `Config::left_edge`.
+    pub fn left_edge(mut self, left_edge: LeftEdgePolicy) -> Self {
+        self.config.left_edge = left_edge;
+        self
+    }
+
+    /// Behavior of `,` once the input is exhausted, see `Config::eof`.
+    pub fn eof(mut self, eof: EofPolicy) -> Self {
+        self.config.eof = eof;
+        self
+    }
+
+    /// Stop execution after this many steps, see `Config::max_steps`.
+    pub fn max_steps(mut self, max_steps: u64) -> Self {
+        self.config.max_steps = Some(max_steps);
+        self
+    }
+
+    /// Stop execution after this much wall-clock time, see `Config::timeout`.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.config.timeout = Some(timeout);
+        self
+    }
+
+    /// Stop execution if the tape grows past the limit, see `Config::memory_limit`.
+    pub fn memory_limit(mut self, memory_limit: MemoryLimit) -> Self {
+        self.config.memory_limit = Some(memory_limit);
+        self
+    }
+
+    /// Stop execution once the token is cancelled, see `ExecutionContext::set_cancellation_token`.
+    pub fn cancellation_token(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Check the options and compile the program, returning an `ExecutionContext` ready to
+    /// `execute` or the first problem found.
+    pub fn build(self) -> Result<ExecutionContext<I, O>, Error> {
+        let program = self.program?;
+        let mut ectx = ExecutionContext::with_config(self.io, &program, self.config);
+        if let ExecutionStatus::Stopped(e) = ectx.status {
+            return Err(e)
+        };
+        if let Some(token) = self.cancellation {
+            ectx.set_cancellation_token(token);
+        };
+        Ok(ectx.with_observer(self.observer))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_build() {
+        let mut input = InMemoryIoCtx::default();
+        input.write_input(b"a").unwrap();
+        let mut ectx = Interpreter::builder()
+            .program(",+.")
+            .io(input)
+            .config(Config { eof: EofPolicy::Error, ..Config::default() })
+            .overflow(OverflowPolicy::Saturate)
+            .build()
+            .unwrap();
+        assert_eq!(ectx.config().eof, EofPolicy::Error);
+        assert_eq!(ectx.config().overflow, OverflowPolicy::Saturate);
+        assert_eq!(ectx.execute(), ExecutionStatus::Terminated);
+        let mut buf = [0u8; 2];
+        assert_eq!(ectx.into_inner().read_output(&mut buf).unwrap(), 1);
+        assert_eq!(buf[0], b'b');
+    }
+
+    #[test]
+    fn test_build_errors() {
+        match Interpreter::builder().program("+]").build() {
+            Err(Error::UnmatchedClose(_)) => {},
+            result => panic!("{:?}", result.map(|ectx| ectx.status)),
+        };
+        match Interpreter::builder().memory_limit(MemoryLimit::Bytes(4)).build() {
+            Err(Error::InvalidConfig(_)) => {},
+            result => panic!("{:?}", result.map(|ectx| ectx.status)),
+        };
+        match Interpreter::builder().program_bytes(b"+\xff.").build() {
+            Err(Error::InvalidEncoding) => {},
+            result => panic!("{:?}", result.map(|ectx| ectx.status)),
+        };
+    }
+
+    #[test]
+    fn test_cancellation_token() {
+        let token = CancellationToken::new();
+        token.cancel();
+        let mut ectx = Interpreter::builder()
+            .program("+[]")
+            .cancellation_token(token)
+            .build()
+            .unwrap();
+        assert_eq!(ectx.execute(), ExecutionStatus::Stopped(Error::Cancelled));
+    }
+}
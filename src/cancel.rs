@@ -0,0 +1,47 @@
+//! Cooperative cancellation of running programs from other threads.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+
+/// A flag shared between an `ExecutionContext` and whoever may want to stop it.
+///
+/// Clones share the same flag, so one clone can be handed to the interpreter while another is
+/// kept to `cancel` it from any thread. The interpreter checks the flag every few thousand steps
+/// and stops with a `Cancelled` status once it is set.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        CancellationToken::default()
+    }
+
+    /// Ask every program using this token (or a clone of it) to stop. There is no undoing this.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_cancel_clone() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        assert!(!clone.is_cancelled());
+        thread::spawn(move || token.cancel()).join().unwrap();
+        assert!(clone.is_cancelled());
+    }
+}
@@ -0,0 +1,159 @@
+//! Static validation of programs before they are run.
+
+use std::fmt;
+
+use crate::token::{Span, Token};
+
+
+/// The kinds of problems that can be found in a program without running it.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DiagnosticKind {
+    /// A `[` with no `]` following it.
+    UnmatchedOpen,
+
+    /// A `]` with no `[` preceding it.
+    UnmatchedClose,
+}
+
+
+/// A single problem found in a program, pointing back at the offending command in the source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+
+    /// Location of the offending command.
+    pub span: Span,
+
+    /// The full source line containing the offending command.
+    pub snippet: String,
+}
+
+
+impl Diagnostic {
+    fn new(kind: DiagnosticKind, span: Span, source: &str) -> Self {
+        let line_start = source[..span.offset].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[span.offset..].find('\n').map_or(source.len(), |i| span.offset + i);
+        Diagnostic {
+            kind,
+            span,
+            snippet: source[line_start..line_end].trim_end_matches('\r').to_string(),
+        }
+    }
+}
+
+
+impl fmt::Display for DiagnosticKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DiagnosticKind::UnmatchedOpen => write!(f, "unmatched '['"),
+            DiagnosticKind::UnmatchedClose => write!(f, "unmatched ']'"),
+        }
+    }
+}
+
+
+/// Renders as the message, the source line and a caret under the offending command, e.g.:
+///
+/// ```text
+/// unmatched '[' at line 1, column 2
+///   +[->+<
+///    ^
+/// ```
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // keep tabs in the padding so the caret lines up however the terminal renders them
+        let padding: String = self.snippet.chars()
+            .take(self.span.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        write!(f, "{} at {}\n  {}\n  {}^", self.kind, self.span, self.snippet, padding)
+    }
+}
+
+
+/// Check a program for errors without running it, returning every problem found in the order
+/// that they appear in the source.
+///
+/// # Examples
+///
+/// ```rust
+/// extern crate bfi;
+///
+/// fn main () {
+///     assert!(bfi::check(",>,<[->+<]>.").is_ok());
+///     let diagnostics = bfi::check("+[->+<]]").unwrap_err();
+///     assert_eq!(diagnostics[0].span.column, 8);
+/// }
+/// ```
+pub fn check(program: &str) -> Result<(), Vec<Diagnostic>> {
+    check_tokens(program, &Token::parse_spanned(program))
+}
+
+
+/// Check a program that has already been parsed from `program` with `Token::parse_spanned`.
+pub(crate) fn check_tokens(program: &str, tokens: &[(Token, Span)]) -> Result<(), Vec<Diagnostic>> {
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    let mut open: Vec<Span> = Vec::new();
+    for &(token, span) in tokens {
+        match token {
+            Token::LoopBeg => open.push(span),
+            Token::LoopEnd if open.pop().is_none() => {
+                diagnostics.push(Diagnostic::new(DiagnosticKind::UnmatchedClose, span, program));
+            },
+            _ => {},
+        };
+    }
+    diagnostics.extend(
+        open.into_iter().map(|span| Diagnostic::new(DiagnosticKind::UnmatchedOpen, span, program))
+    );
+    diagnostics.sort_by_key(|d| d.span.offset);
+    match diagnostics.is_empty() {
+        true => Ok(()),
+        false => Err(diagnostics),
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_valid_program() {
+        assert_eq!(check("+[>[-]<]"), Ok(()));
+    }
+
+    #[test]
+    fn test_brackets_in_comments() {
+        assert_eq!(check("+[>[-]<]comments ] are [ ignored? no"), Err(vec![
+            Diagnostic::new(
+                DiagnosticKind::UnmatchedClose,
+                Span { offset: 17, line: 1, column: 18 },
+                "+[>[-]<]comments ] are [ ignored? no",
+            ),
+            Diagnostic::new(
+                DiagnosticKind::UnmatchedOpen,
+                Span { offset: 23, line: 1, column: 24 },
+                "+[>[-]<]comments ] are [ ignored? no",
+            ),
+        ]));
+    }
+
+    #[test]
+    fn test_all_errors_reported() {
+        let diagnostics = check("[\n]]\n\t[[]").unwrap_err();
+        let kinds: Vec<DiagnosticKind> = diagnostics.iter().map(|d| d.kind).collect();
+        assert_eq!(kinds, vec![DiagnosticKind::UnmatchedClose, DiagnosticKind::UnmatchedOpen]);
+        assert_eq!((diagnostics[0].span.line, diagnostics[0].span.column), (2, 2));
+        assert_eq!((diagnostics[1].span.line, diagnostics[1].span.column), (3, 2));
+    }
+
+    #[test]
+    fn test_display() {
+        let diagnostics = check("++\n>[-<+>\n").unwrap_err();
+        assert_eq!(
+            format!("{}", diagnostics[0]),
+            "unmatched '[' at line 2, column 2\n  >[-<+>\n   ^",
+        );
+    }
+}
@@ -0,0 +1,344 @@
+//! Options controlling how programs are executed.
+
+use std::mem;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::error::Error;
+
+
+/// Size of each cell on the tape.
+///
+/// Whatever the width, `.` outputs the value of the cell modulo 256 (its low byte) and `,` stores
+/// the byte read as a value on `[0, 255]`.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum CellWidth {
+    /// Unsigned cells holding values on `[0, 255]`, the default.
+    #[default]
+    U8,
+
+    /// Unsigned cells holding values on `[0, 65535]`.
+    U16,
+
+    /// Unsigned cells holding values on `[0, 4294967295]`.
+    U32,
+
+    /// Signed cells holding any `i64`.
+    I64,
+}
+
+
+impl CellWidth {
+    /// Get the `CellWidth` with the given number of bits, if there is one.
+    pub fn from_bits(bits: u32) -> Option<Self> {
+        match bits {
+            8 => Some(CellWidth::U8),
+            16 => Some(CellWidth::U16),
+            32 => Some(CellWidth::U32),
+            64 => Some(CellWidth::I64),
+            _ => None,
+        }
+    }
+
+    /// Number of bits in a cell.
+    pub fn bits(self) -> u32 {
+        match self {
+            CellWidth::U8 => 8,
+            CellWidth::U16 => 16,
+            CellWidth::U32 => 32,
+            CellWidth::I64 => 64,
+        }
+    }
+
+    /// Smallest value a cell can hold.
+    pub fn min(self) -> i64 {
+        match self {
+            CellWidth::I64 => i64::MIN,
+            _ => 0,
+        }
+    }
+
+    /// Largest value a cell can hold.
+    pub fn max(self) -> i64 {
+        match self {
+            CellWidth::I64 => i64::MAX,
+            _ => (1 << self.bits()) - 1,
+        }
+    }
+
+    /// Wrap any value into the range of a cell. Together with `i64` wrapping arithmetic, this
+    /// gives arithmetic modulo the cell size.
+    pub fn wrap(self, value: i64) -> i64 {
+        match self {
+            CellWidth::I64 => value,
+            _ => value & self.max(),
+        }
+    }
+}
+
+
+/// Parse from the number of bits, e.g. `"16"`.
+impl FromStr for CellWidth {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse::<u32>().ok()
+            .and_then(CellWidth::from_bits)
+            .ok_or_else(|| format!("invalid cell size '{}', expected one of 8, 16, 32, 64", s))
+    }
+}
+
+
+/// What happens when `+` or `-` take a cell past the range of its `CellWidth`.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum OverflowPolicy {
+    /// Wrap around to the other end of the range, the default.
+    #[default]
+    Wrap,
+
+    /// Stay at the end of the range.
+    Saturate,
+
+    /// Stop with an `error::Error`.
+    Error,
+}
+
+
+/// Parse from the lowercase name of the policy, e.g. `"saturate"`.
+impl FromStr for OverflowPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wrap" => Ok(OverflowPolicy::Wrap),
+            "saturate" => Ok(OverflowPolicy::Saturate),
+            "error" => Ok(OverflowPolicy::Error),
+            _ => Err(format!("invalid overflow policy '{}', expected wrap, saturate or error", s)),
+        }
+    }
+}
+
+
+/// What happens when the data pointer is moved past either end of a bounded tape.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum EdgePolicy {
+    /// Stop with an `error::Error`, the default.
+    #[default]
+    Error,
+
+    /// Continue from the other end of the tape.
+    Wrap,
+
+    /// Stay at the end of the tape.
+    Clamp,
+}
+
+
+/// Parse from the lowercase name of the policy, e.g. `"wrap"`.
+impl FromStr for EdgePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(EdgePolicy::Error),
+            "wrap" => Ok(EdgePolicy::Wrap),
+            "clamp" => Ok(EdgePolicy::Clamp),
+            _ => Err(format!("invalid edge policy '{}', expected error, wrap or clamp", s)),
+        }
+    }
+}
+
+
+/// What happens when the data pointer is moved to the left of the starting cell of an unbounded
+/// tape.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum LeftEdgePolicy {
+    /// Grow the tape to the left, the default.
+    #[default]
+    Grow,
+
+    /// Stop with an `error::Error`.
+    Error,
+}
+
+
+/// Parse from the lowercase name of the policy, e.g. `"grow"`.
+impl FromStr for LeftEdgePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "grow" => Ok(LeftEdgePolicy::Grow),
+            "error" => Ok(LeftEdgePolicy::Error),
+            _ => Err(format!("invalid left edge policy '{}', expected grow or error", s)),
+        }
+    }
+}
+
+
+/// What `,` does to the current cell when there is no input left.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum EofPolicy {
+    /// Leave the cell as it is, the default.
+    #[default]
+    Unchanged,
+
+    /// Set the cell to zero.
+    Zero,
+
+    /// Set every bit of the cell, i.e. 255 for `CellWidth::U8` and -1 for `CellWidth::I64`.
+    AllOnes,
+
+    /// Stop with an `error::Error`.
+    Error,
+}
+
+
+/// Parse from the lowercase name of the policy, e.g. `"all-ones"`.
+impl FromStr for EofPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "unchanged" => Ok(EofPolicy::Unchanged),
+            "zero" => Ok(EofPolicy::Zero),
+            "all-ones" => Ok(EofPolicy::AllOnes),
+            "error" => Ok(EofPolicy::Error),
+            _ => Err(format!(
+                "invalid EOF policy '{}', expected unchanged, zero, all-ones or error", s,
+            )),
+        }
+    }
+}
+
+
+/// Largest the tape is allowed to grow, counting every cell from the leftmost to the rightmost
+/// reached.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MemoryLimit {
+    /// Limit on the number of cells.
+    Cells(usize),
+
+    /// Limit on the memory taken by the cells, as stored by the interpreter. Each cell takes
+    /// 8 bytes whatever its `CellWidth`.
+    Bytes(usize),
+}
+
+
+impl MemoryLimit {
+    /// Number of cells allowed by the limit.
+    pub fn cells(self) -> usize {
+        match self {
+            MemoryLimit::Cells(n) => n,
+            MemoryLimit::Bytes(n) => n / mem::size_of::<i64>(),
+        }
+    }
+}
+
+
+/// All options for an execution. The defaults match the behavior described in the README.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Config {
+    pub cell_width: CellWidth,
+    pub overflow: OverflowPolicy,
+
+    /// Number of cells on a bounded tape, running from cell 0 to `tape_size - 1`, or `None` for
+    /// a tape that grows as far as the program needs.
+    pub tape_size: Option<usize>,
+
+    /// Behavior at both ends of a bounded tape.
+    pub tape_edge: EdgePolicy,
+
+    /// Behavior to the left of the starting cell of an unbounded tape. Bounded tapes always use
+    /// `tape_edge`.
+    pub left_edge: LeftEdgePolicy,
+
+    /// Behavior of `,` once the input is exhausted.
+    pub eof: EofPolicy,
+
+    /// Number of steps after which execution is stopped, if any. Each operation executed is a
+    /// step, see `ir::Op`.
+    pub max_steps: Option<u64>,
+
+    /// Wall-clock time after which execution is stopped, if any. The clock is only checked every
+    /// few thousand steps, so execution may run slightly over.
+    pub timeout: Option<Duration>,
+
+    /// Largest the tape is allowed to grow, if there is a limit.
+    pub memory_limit: Option<MemoryLimit>,
+}
+
+
+impl Config {
+    /// Check that the options make sense together.
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.tape_size == Some(0) {
+            let reason = "a bounded tape needs at least one cell";
+            return Err(Error::InvalidConfig(reason.to_string()));
+        };
+        if matches!(self.tape_size, Some(size) if size > isize::MAX as usize) {
+            let reason = format!("a bounded tape can have at most {} cells", isize::MAX);
+            return Err(Error::InvalidConfig(reason));
+        };
+        if matches!(self.memory_limit, Some(limit) if limit.cells() == 0) {
+            let reason = "the memory limit must allow at least one cell";
+            return Err(Error::InvalidConfig(reason.to_string()));
+        };
+        Ok(())
+    }
+
+    /// Whether the data pointer can be moved by any amount without stopping the program or
+    /// ending up anywhere other than where the moves add up to.
+    pub(crate) fn moves_are_free(&self) -> bool {
+        if self.memory_limit.is_some() {
+            return false
+        };
+        match self.tape_size {
+            None => self.left_edge == LeftEdgePolicy::Grow,
+            Some(_) => self.tape_edge == EdgePolicy::Wrap,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!(CellWidth::U8.wrap(-1), 255);
+        assert_eq!(CellWidth::U8.wrap(256), 0);
+        assert_eq!(CellWidth::U16.wrap(-1), 65535);
+        assert_eq!(CellWidth::U32.wrap(1 << 32), 0);
+        assert_eq!(CellWidth::I64.wrap(-1), -1);
+        assert_eq!(CellWidth::I64.wrap(i64::MAX.wrapping_add(1)), i64::MIN);
+    }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!("32".parse::<CellWidth>(), Ok(CellWidth::U32));
+        assert!("7".parse::<CellWidth>().is_err());
+        assert!("eight".parse::<CellWidth>().is_err());
+        assert_eq!("saturate".parse::<OverflowPolicy>(), Ok(OverflowPolicy::Saturate));
+        assert!("clamp".parse::<OverflowPolicy>().is_err());
+        assert_eq!("clamp".parse::<EdgePolicy>(), Ok(EdgePolicy::Clamp));
+        assert_eq!("error".parse::<LeftEdgePolicy>(), Ok(LeftEdgePolicy::Error));
+        assert_eq!("all-ones".parse::<EofPolicy>(), Ok(EofPolicy::AllOnes));
+        assert!("255".parse::<EofPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(Config::default().validate(), Ok(()));
+        assert!(Config { tape_size: Some(0), ..Config::default() }.validate().is_err());
+        let tape_size = Some(usize::MAX);
+        assert!(Config { tape_size, ..Config::default() }.validate().is_err());
+        let tape_size = Some(isize::MAX as usize);
+        assert_eq!(Config { tape_size, ..Config::default() }.validate(), Ok(()));
+        let memory_limit = Some(MemoryLimit::Bytes(7));
+        assert!(Config { memory_limit, ..Config::default() }.validate().is_err());
+        let memory_limit = Some(MemoryLimit::Bytes(8));
+        assert_eq!(Config { memory_limit, ..Config::default() }.validate(), Ok(()));
+    }
+}
@@ -0,0 +1,189 @@
+//! The ways in which loading or running a program can go wrong.
+
+use std::error;
+use std::fmt;
+use std::io;
+
+use crate::check::{Diagnostic, DiagnosticKind};
+use crate::token::Span;
+
+
+/// Where in a program something happened: the position of the operation in the compiled program
+/// (see `ir::Program`) and, when known, the location of the command in the source.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Position {
+    pub op: usize,
+    pub span: Option<Span>,
+}
+
+
+/// Renders as e.g. `program position 3 (line 1, column 4)`.
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "program position {}", self.op)?;
+        match self.span {
+            Some(span) => write!(f, " ({})", span),
+            None => Ok(()),
+        }
+    }
+}
+
+
+/// Everything that can stop a program from being loaded or from running to completion.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// A `[` with no matching `]`. If there are several unmatched brackets, this is the first of
+    /// them, see `check` for all of them.
+    UnmatchedOpen(Diagnostic),
+
+    /// A `]` with no matching `[`, see `UnmatchedOpen`.
+    UnmatchedClose(Diagnostic),
+
+    /// The program is not valid UTF-8.
+    InvalidEncoding,
+
+    /// The options in the `Config` do not make sense together, for the given reason.
+    InvalidConfig(String),
+
+    /// A save could not be restored, for the given reason.
+    InvalidSave(String),
+
+    /// Reading input or writing output failed.
+    Io { kind: io::ErrorKind, message: String, position: Option<Position> },
+
+    /// The interactive session at a breakpoint failed.
+    Repl(String),
+
+    /// A cell went past the range of its `CellWidth` under `OverflowPolicy::Error`.
+    Overflow { cell: isize, position: Position },
+
+    /// The data pointer was moved past an edge of the tape that does not let it through.
+    OffTape { cell: isize, position: Position },
+
+    /// Input was requested once there was none left, under `EofPolicy::Error`.
+    InputExhausted { position: Position },
+
+    /// The tape would have grown past the memory limit to reach the cell.
+    MemoryLimit { cell: isize, position: Position },
+
+    /// The step limit ran out after the given number of steps.
+    StepLimit { steps: u64 },
+
+    /// The time limit ran out after the given number of steps.
+    Timeout { steps: u64 },
+
+    /// Execution was stopped through a `CancellationToken`.
+    Cancelled,
+
+    /// The program has not finished running, so there is no result yet.
+    Unfinished,
+}
+
+
+impl Error {
+    /// Wrap an error from reading input or writing output.
+    pub(crate) fn io(e: io::Error, position: Option<Position>) -> Self {
+        Error::Io { kind: e.kind(), message: e.to_string(), position }
+    }
+
+    /// Whether this is a 'me' problem (e.g. HTTP 500s) rather than a 'you' problem with the
+    /// program or its options (e.g. HTTP 400s).
+    pub fn is_internal(&self) -> bool {
+        matches!(self, Error::Io { .. } | Error::Repl(_))
+    }
+
+    /// Where in the program the error happened, for errors that happen at a single command.
+    pub fn position(&self) -> Option<Position> {
+        match self {
+            Error::Io { position, .. } => *position,
+            Error::Overflow { position, .. }
+            | Error::OffTape { position, .. }
+            | Error::InputExhausted { position }
+            | Error::MemoryLimit { position, .. } => Some(*position),
+            _ => None,
+        }
+    }
+
+    /// Location in the source of the command the error is about, if there is one.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Error::UnmatchedOpen(d) | Error::UnmatchedClose(d) => Some(d.span),
+            _ => self.position().and_then(|position| position.span),
+        }
+    }
+}
+
+
+impl From<Diagnostic> for Error {
+    fn from(diagnostic: Diagnostic) -> Self {
+        match diagnostic.kind {
+            DiagnosticKind::UnmatchedOpen => Error::UnmatchedOpen(diagnostic),
+            DiagnosticKind::UnmatchedClose => Error::UnmatchedClose(diagnostic),
+        }
+    }
+}
+
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::UnmatchedOpen(d) | Error::UnmatchedClose(d) => write!(f, "{}", d),
+            Error::InvalidEncoding => write!(f, "program is not valid UTF-8"),
+            Error::InvalidConfig(reason) => write!(f, "invalid options: {}", reason),
+            Error::InvalidSave(reason) => write!(f, "invalid save: {}", reason),
+            Error::Io { message, position: Some(position), .. } => {
+                write!(f, "{} at {}", message, position)
+            },
+            Error::Io { message, position: None, .. } => write!(f, "{}", message),
+            Error::Repl(message) => write!(f, "{}", message),
+            Error::Overflow { cell, position } => {
+                write!(f, "cell {} overflowed at {}", cell, position)
+            },
+            Error::OffTape { cell, position } => write!(
+                f,
+                "data pointer moved to cell {}, past the {} of the tape at {}",
+                cell, if *cell < 0 { "start" } else { "end" }, position,
+            ),
+            Error::InputExhausted { position } => write!(f, "input exhausted at {}", position),
+            Error::MemoryLimit { cell, position } => {
+                write!(f, "memory limit exceeded reaching cell {} at {}", cell, position)
+            },
+            Error::StepLimit { steps } => write!(f, "step limit reached after {} steps", steps),
+            Error::Timeout { steps } => write!(f, "time limit reached after {} steps", steps),
+            Error::Cancelled => write!(f, "execution cancelled"),
+            Error::Unfinished => write!(f, "program has not finished running"),
+        }
+    }
+}
+
+
+impl error::Error for Error {}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let position = Position { op: 4, span: Some(Span { offset: 5, line: 2, column: 4 }) };
+        let e = Error::Overflow { cell: 0, position };
+        assert_eq!(e.to_string(), "cell 0 overflowed at program position 4 (line 2, column 4)");
+        let e = Error::OffTape { cell: -1, position: Position { op: 2, span: None } };
+        assert_eq!(
+            e.to_string(), "data pointer moved to cell -1, past the start of the tape at program \
+            position 2",
+        );
+        assert_eq!(e.span(), None);
+    }
+
+    #[test]
+    fn test_from_diagnostic() {
+        let e = Error::from(crate::check("+]").unwrap_err().remove(0));
+        match &e {
+            Error::UnmatchedClose(d) => assert_eq!(e.span(), Some(d.span)),
+            e => panic!("{:?}", e),
+        };
+        assert!(!e.is_internal());
+    }
+}
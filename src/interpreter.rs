@@ -1,18 +1,28 @@
 //! The interpreter resposible for executing programs.
 
-use std::cell::RefMut;
 use std::default::Default;
 use std::fmt::{self, Debug};
-use std::io::{Read, Write};
+use std::io;
+use std::mem;
+use std::time::Instant;
 
+use crate::cancel::CancellationToken;
+use crate::config::{
+    Config, EdgePolicy, EofPolicy, LeftEdgePolicy, MemoryLimit, OverflowPolicy,
+};
+use crate::error::{Error, Position};
 use crate::ioctx::IoCtx;
+use crate::ir::{Op, Program};
+use crate::observer::{ExecutionObserver, NoObserver};
+use crate::optimize;
 use crate::repl;
-use crate::token::Token;
+use crate::save;
+use crate::tape::Tape;
 
 
 /// Current status of the interpreter.
-#[derive(Debug, Copy, Clone, PartialEq)]
-pub enum ExecutionStatus<T> {
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExecutionStatus {
     /// Indicates a newly created `ExecutionContext` that has not begun execution of a program.
     NotStarted,
 
@@ -22,211 +32,664 @@ pub enum ExecutionStatus<T> {
     /// Indicates termination of the program without errors.
     Terminated,
 
-    /// Indicates termination with a 'you' problem (e.g. HTTP 400s).
-    ProgramError(T),
+    /// Indicates that the program was stopped, or could not be started, because of the error.
+    /// See `Error::is_internal` to tell problems with the program from problems running it.
+    Stopped(Error),
+}
+
+
+/// Number of steps between checks of the clock and of the `CancellationToken`, if any.
+const CHECK_INTERVAL: u64 = 4096;
+
 
-    /// Indicates termination with a 'me' problem (e.g. HTTP 500s).
-    InternalError(T),
+/// Copy of the machine state of an `ExecutionContext` at some point of its execution, see
+/// `ExecutionContext::snapshot`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub tape: Tape,
+    pub data_ptr: isize,
+    pub program_ptr: usize,
+    pub loop_stack: Vec<usize>,
+    pub steps: u64,
+    pub status: ExecutionStatus,
 }
 
 
-/// The internal state of a BrainF\*ck program.
+/// The internal state of a BrainF\*ck program, reading input from and writing output to an I/O
+/// context of type `I` and reporting what it does to an observer of type `O` (see
+/// `observer::ExecutionObserver`).
 ///
-/// Note that only the `status` is visible. If you are interested in inspecting the state during
-/// execution, use the REPL by putting a debug breakpoint (`%`) in your program!
-pub struct ExecutionContext<'a> {
-    pub status: ExecutionStatus<String>,
-    ctx: Option<RefMut<'a, Box<dyn IoCtx>>>,
-    data: Vec<u8>,
-    data_ptr: usize,
-    program: Vec<Token>,
+/// The context is owned by the `ExecutionContext` and handed back by `into_inner`. To keep hold of
+/// it instead, pass a `&mut` reference to it (or a `Box<dyn IoCtx>` for dynamic dispatch), both of
+/// which are `IoCtx` too.
+///
+/// Only the `status` can be changed from outside, the rest of the state is read through the
+/// accessors below or taken all at once as a `Snapshot`. To poke around interactively during
+/// execution instead, use the REPL by putting a debug breakpoint (`%`) in your program!
+pub struct ExecutionContext<I: IoCtx, O: ExecutionObserver = NoObserver> {
+    pub status: ExecutionStatus,
+    ctx: I,
+    observer: O,
+    config: Config,
+    data: Tape,
+    data_ptr: isize,
+    source: String,
+    program: Program,
     program_ptr: usize,
     loop_stack: Vec<usize>,
+    steps: u64,
+    started: Option<Instant>,
+    cancellation: Option<CancellationToken>,
 }
 
 
-impl<'a> Debug for ExecutionContext<'a> {
+impl<I: IoCtx, O: ExecutionObserver> Debug for ExecutionContext<I, O> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "data: {:?}\ndata_ptr: {:?}\nprogram_ptr: {:?}\nloop_stack: {:?}\nstatus: {:?}",
-            self.data, self.data_ptr, self.program_ptr, self.loop_stack, self.status,
+            "data: {:?}\ndata_ptr: {:?}\nprogram_ptr: {:?}{}\nloop_stack: {:?}\nstatus: {:?}",
+            self.data,
+            self.data_ptr,
+            self.program_ptr,
+            self.location(),
+            self.loop_stack,
+            self.status,
         )
     }
 }
 
 
-impl<'a> Default for ExecutionContext<'a> {
+impl<I: IoCtx + Default> Default for ExecutionContext<I> {
     fn default() -> Self {
+        ExecutionContext::with_program(I::default(), Program::default())
+    }
+}
+
+
+impl<I: IoCtx> ExecutionContext<I> {
+    /// Create a new `ExecutionContext` with the provided I/O context and program. Typically called
+    /// immediately before `execute`.
+    ///
+    /// Brackets are matched here, before any of the program is run. If the program contains an
+    /// unmatched `[` or `]` the returned context has a `Stopped` status and `execute` will
+    /// return it immediately.
+    pub fn new(ictx: I, program: &str) -> Self {
+        ExecutionContext::with_config(ictx, program, Config::default())
+    }
+
+    /// Create a new `ExecutionContext` like `new`, using the options in `config` rather than the
+    /// defaults. Invalid options also result in a `Stopped` status.
+    pub fn with_config(ictx: I, program: &str, config: Config) -> Self {
+        let mut ectx = ExecutionContext::with_program(ictx, Program::default());
+        if let Err(e) = config.validate() {
+            ectx.status = ExecutionStatus::Stopped(e);
+            return ectx
+        };
+        ectx.config = config;
+        ectx.load_program(program);
+        ectx
+    }
+
+    /// Create a new `ExecutionContext` to run an already compiled program, as-is.
+    pub(crate) fn with_program(ictx: I, program: Program) -> Self {
         ExecutionContext {
             status: ExecutionStatus::NotStarted,
-            ctx: None,
-            data: vec![0],
+            ctx: ictx,
+            observer: NoObserver,
+            config: Config::default(),
+            data: Tape::default(),
             data_ptr: 0,
-            program: vec![],
+            source: String::new(),
+            program,
             program_ptr: 0,
             loop_stack: vec![],
+            steps: 0,
+            started: None,
+            cancellation: None,
+        }
+    }
+
+    /// Restore an execution saved by `save`, with the provided I/O context. Any input pending in
+    /// the save is returned alongside rather than written to the context, which may not support
+    /// `IoCtx::write_input`, leaving it to the caller to feed it to the program.
+    ///
+    /// The time budget, if any, starts over and no `CancellationToken` is set.
+    pub fn restore(ictx: I, saved: &[u8]) -> Result<(Self, Option<Vec<u8>>), Error> {
+        let mut reader = save::Reader::new(saved)?;
+        let source = reader.string()?;
+        let config = reader.config()?;
+        let mut ectx = ExecutionContext::with_config(ictx, &source, config);
+        if let ExecutionStatus::Stopped(e) = ectx.status {
+            return Err(save::invalid_save(format!("saved program cannot be loaded: {}", e)));
+        };
+        let start = reader.isize()?;
+        let mut cells = Vec::new();
+        let width = ectx.config.cell_width;
+        for _ in 0..reader.usize()? {
+            let cell = reader.i64()?;
+            if cell < width.min() || cell > width.max() {
+                let reason = format!("saved cell {} does not fit in {} bits", cell, width.bits());
+                return Err(save::invalid_save(reason));
+            };
+            cells.push(cell);
+        }
+        ectx.data = Tape::from_cells(start, cells)
+            .ok_or_else(|| save::invalid_save("saved tape is invalid"))?;
+        if let Some(size) = ectx.config.tape_size {
+            if ectx.data.start() < 0 || ectx.data.end() as usize > size {
+                return Err(save::invalid_save("saved tape does not fit in the bounded tape"));
+            };
+        };
+        if matches!(ectx.config.memory_limit, Some(limit) if ectx.data.len() > limit.cells()) {
+            return Err(save::invalid_save("saved tape is larger than the memory limit"));
+        };
+        ectx.data_ptr = reader.isize()?;
+        if ectx.data_ptr < ectx.data.start() || ectx.data_ptr >= ectx.data.end() {
+            return Err(save::invalid_save("saved data pointer is off the tape"));
+        };
+        ectx.program_ptr = reader.usize()?;
+        if ectx.program_ptr > ectx.program.ops().len() {
+            return Err(save::invalid_save("saved program pointer is past the end of the program"));
+        };
+        for _ in 0..reader.usize()? {
+            let position = reader.usize()?;
+            match ectx.program.ops().get(position) {
+                Some(Op::JumpIfZero(_)) => ectx.loop_stack.push(position),
+                _ => return Err(save::invalid_save("saved loop stack does not match the program")),
+            };
         }
+        ectx.steps = reader.u64()?;
+        ectx.status = reader.status()?;
+        ectx.started = Some(Instant::now());
+        let input = match reader.u8()? {
+            0 => None,
+            1 => Some(reader.bytes()?.to_vec()),
+            tag => return Err(save::invalid_save(format!("invalid pending input {} in save", tag))),
+        };
+        reader.finish()?;
+        Ok((ectx, input))
     }
 }
 
 
-impl<'a> ExecutionContext<'a> {
-    /// Create a new `ExecutionContext` with the provided I/O context and program. Typically called
-    /// immediately before `execute`.
-    pub fn new(ictx: RefMut<'a, Box<dyn IoCtx>>, program: &str) -> Self {
+impl<I: IoCtx, O: ExecutionObserver> ExecutionContext<I, O> {
+    /// Attach an observer, replacing the current one. Pass a `&mut` reference to keep hold of
+    /// the observer, or take it back with `into_observer`.
+    pub fn with_observer<P: ExecutionObserver>(self, observer: P) -> ExecutionContext<I, P> {
         ExecutionContext {
-            ctx: Some(ictx),
-            program: Token::parse_str(program),
-            ..ExecutionContext::default()
+            status: self.status,
+            ctx: self.ctx,
+            observer,
+            config: self.config,
+            data: self.data,
+            data_ptr: self.data_ptr,
+            source: self.source,
+            program: self.program,
+            program_ptr: self.program_ptr,
+            loop_stack: self.loop_stack,
+            steps: self.steps,
+            started: self.started,
+            cancellation: self.cancellation,
+        }
+    }
+
+    /// The observer, e.g. to look at what it recorded so far.
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    /// Give back the observer, e.g. to read what it recorded.
+    pub fn into_observer(self) -> O {
+        self.observer
+    }
+
+    /// Give back the I/O context, e.g. to read the output written to an `InMemoryIoCtx`.
+    pub fn into_inner(self) -> I {
+        self.ctx
+    }
+
+    /// The I/O context, e.g. to take output from an `InMemoryIoCtx` while the program runs.
+    pub fn get_mut(&mut self) -> &mut I {
+        &mut self.ctx
+    }
+
+    fn load_program(&mut self, program: &str) {
+        self.source = program.to_string();
+        match Program::compile(program) {
+            Ok(program) => self.program = optimize::optimize(&program, &self.config),
+            Err(e) => self.status = ExecutionStatus::Stopped(e),
+        };
+    }
+
+    /// Save the execution so far as bytes that `restore` turns back into an `ExecutionContext`
+    /// that carries on exactly where this one stands, possibly in another process.
+    ///
+    /// The save holds the source of the program, the config, the machine state (see `Snapshot`)
+    /// and the input not yet read by the program, if the I/O context can tell what that is (see
+    /// `IoCtx::pending_input`). Output already written is not saved.
+    pub fn save(&mut self) -> Vec<u8> {
+        let mut writer = save::Writer::new();
+        writer.bytes(self.source.as_bytes());
+        writer.config(&self.config);
+        writer.isize(self.data.start());
+        writer.usize(self.data.len());
+        for cell in self.data.to_vec() {
+            writer.i64(cell);
+        }
+        writer.isize(self.data_ptr);
+        writer.usize(self.program_ptr);
+        writer.usize(self.loop_stack.len());
+        for &position in &self.loop_stack {
+            writer.usize(position);
+        }
+        writer.u64(self.steps);
+        writer.status(&self.status);
+        match self.ctx.pending_input() {
+            Some(input) => { writer.u8(1); writer.bytes(&input) },
+            None => writer.u8(0),
+        };
+        writer.finish()
+    }
+
+    /// Position of the current command, for errors raised while executing it.
+    fn position(&self) -> Position {
+        Position { op: self.program_ptr, span: self.program.spans().get(self.program_ptr).copied() }
+    }
+
+    /// Location in the source of the current command, formatted for use in messages.
+    fn location(&self) -> String {
+        match self.program.spans().get(self.program_ptr) {
+            Some(span) => format!(" ({})", span),
+            None => String::new(),
+        }
+    }
+
+    /// Stop execution with a `Cancelled` status once the token is cancelled, which may be done
+    /// from another thread while `execute` is running.
+    pub fn set_cancellation_token(&mut self, token: CancellationToken) {
+        self.cancellation = Some(token);
+    }
+
+    /// Number of steps executed so far.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// The tape, with every cell reached so far.
+    pub fn tape(&self) -> &Tape {
+        &self.data
+    }
+
+    /// Index on the tape of the current cell.
+    pub fn data_ptr(&self) -> isize {
+        self.data_ptr
+    }
+
+    /// The program being executed, as compiled and optimized for the config.
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    /// Position in `program` of the next operation to execute.
+    pub fn program_ptr(&self) -> usize {
+        self.program_ptr
+    }
+
+    /// Positions in `program` of the `JumpIfZero` of every loop currently being executed, from
+    /// the outermost in.
+    pub fn loop_stack(&self) -> &[usize] {
+        &self.loop_stack
+    }
+
+    /// The options used for execution.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Copy of the current machine state.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            tape: self.data.clone(),
+            data_ptr: self.data_ptr,
+            program_ptr: self.program_ptr,
+            loop_stack: self.loop_stack.clone(),
+            steps: self.steps,
+            status: self.status.clone(),
         }
     }
 
     /// Execute the program and return the resulting `ExecutionStatus`.
     ///
     /// The output of the program itself is obtained in other ways, see `ioctx::IoCtx`.
-    pub fn execute(&mut self) -> ExecutionStatus<String> {
-        self.run();
+    pub fn execute(&mut self) -> ExecutionStatus {
+        while self.advance() {}
         self.status.clone()
     }
 
-    fn run(&mut self) {
-        loop {
-            match self.status {
-                ExecutionStatus::Terminated => return self.cleanup(),
-                ExecutionStatus::ProgramError(_) | ExecutionStatus::InternalError(_) => return,
-                ExecutionStatus::NotStarted => self.status = ExecutionStatus::InProgress,
-                ExecutionStatus::InProgress => {
-                    match self.program.get(self.program_ptr) {
-                        Some(&cmd) => self.run_command(cmd),
-                        None => self.status = ExecutionStatus::Terminated,
-                    };
-                },
+    /// Execute a single operation of the program, starting it if it has not been started, and
+    /// return the resulting `ExecutionStatus`. Stepping a program that has stopped does nothing.
+    ///
+    /// An operation may stand for several commands of the source, see `ir::Op`.
+    pub fn step(&mut self) -> ExecutionStatus {
+        self.step_n(1)
+    }
+
+    /// Execute up to `n` operations of the program like `step`, stopping early if the program
+    /// does, and return the resulting `ExecutionStatus`.
+    pub fn step_n(&mut self, n: u64) -> ExecutionStatus {
+        for _ in 0..n {
+            if !self.advance() {
+                break
             };
         }
+        self.status.clone()
+    }
+
+    /// Execute operations of the program like `step` until the predicate holds or the program
+    /// stops, and return the resulting `ExecutionStatus`. The predicate is checked before every
+    /// operation, so nothing is executed if it holds to begin with.
+    pub fn run_until<P>(&mut self, mut predicate: P) -> ExecutionStatus
+    where
+        P: FnMut(&Self) -> bool,
+    {
+        while !predicate(self) && self.advance() {}
+        self.status.clone()
+    }
+
+    /// Execute the next operation, returning `false` without executing anything if the program
+    /// has stopped.
+    fn advance(&mut self) -> bool {
+        if self.status == ExecutionStatus::NotStarted {
+            self.started = Some(Instant::now());
+            self.status = ExecutionStatus::InProgress;
+        };
+        if self.status != ExecutionStatus::InProgress {
+            return false
+        };
+        if let Some(e) = self.over_budget() {
+            self.status = ExecutionStatus::Stopped(e);
+            return false
+        };
+        if self.cancelled() {
+            self.status = ExecutionStatus::Stopped(Error::Cancelled);
+            return false
+        };
+        match self.program.ops().get(self.program_ptr) {
+            Some(&op) => {
+                self.run_op(op);
+                true
+            },
+            None => {
+                self.status = match self.flush() {
+                    Ok(()) => ExecutionStatus::Terminated,
+                    Err(e) => ExecutionStatus::Stopped(Error::io(e, None)),
+                };
+                false
+            },
+        }
     }
 
-    fn run_command(&mut self, command: Token) {
-        match command {
-            Token::PtrInc => self.pointer_increment(),
-            Token::PtrDec => self.pointer_decrement(),
-            Token::ValInc => self.value_increment(),
-            Token::ValDec => self.value_decrement(),
-            Token::PutChar => self.put_character(),
-            Token::GetChar => self.get_character(),
-            Token::LoopBeg => self.loop_enter(),
-            Token::LoopEnd => self.loop_exit(),
-            Token::DebugDump => eprintln!("{:?}", self),
-            Token::DebugBreakpoint => self.run_interactive(),
-        };
-        match command {
-            Token::LoopEnd => {} // special case that sets the program pointer itself
+    /// The error for the step or time budget in the config, if either has run out.
+    fn over_budget(&self) -> Option<Error> {
+        if matches!(self.config.max_steps, Some(max) if self.steps >= max) {
+            return Some(Error::StepLimit { steps: self.steps })
+        };
+        match (self.config.timeout, self.started) {
+            (Some(timeout), Some(started))
+                if self.steps.is_multiple_of(CHECK_INTERVAL) && started.elapsed() >= timeout =>
+            {
+                Some(Error::Timeout { steps: self.steps })
+            },
+            _ => None,
+        }
+    }
+
+    fn cancelled(&self) -> bool {
+        match &self.cancellation {
+            Some(token) if self.steps.is_multiple_of(CHECK_INTERVAL) => token.is_cancelled(),
+            _ => false,
+        }
+    }
+
+    fn run_op(&mut self, op: Op) {
+        self.observer.on_op(self.program_ptr, op);
+        self.steps += 1;
+        match op {
+            Op::Add(n) => self.value_add(i128::from(n)),
+            Op::Move(n) => self.pointer_move(n),
+            Op::Out => self.put_character(0),
+            Op::In => self.get_character(),
+            Op::JumpIfZero(target) => self.loop_enter(target),
+            Op::JumpIfNonZero(target) => self.loop_exit(target),
+            Op::DebugDump => eprintln!("{:?}", self),
+            Op::DebugBreakpoint => self.run_interactive(),
+            Op::Clear => *self.current_cell() = 0,
+            Op::MulAdd(offset, factor) => self.multiply_add(offset, factor),
+            Op::Scan(n) => self.scan(n),
+            Op::AddAt(offset, n) => self.value_add_at(offset, i128::from(n)),
+            Op::OutAt(offset) => self.put_character(offset),
+        };
+        match op {
+            // jumps and scans set the program pointer themselves
+            Op::JumpIfZero(_) | Op::JumpIfNonZero(_) | Op::Scan(_) => {},
             _ => self.program_ptr += 1,
         };
     }
 
     fn run_interactive(&mut self) {
-        let program_ptr_before = self.program_ptr;
         for cmd in repl::ReplInstance::default() {
             match cmd {
-                repl::ReplResult::Command(cmd) => self.run_command(cmd),
+                repl::ReplResult::Line(line) => self.run_subprogram(line.as_str()),
                 repl::ReplResult::Quit => {
                     self.status = ExecutionStatus::Terminated;
                     return
                 },
                 repl::ReplResult::Error(e) => {
-                    self.status = ExecutionStatus::InternalError(e);
+                    self.status = ExecutionStatus::Stopped(Error::Repl(e));
                     return
                 },
             };
+            if self.status != ExecutionStatus::InProgress {
+                return
+            };
         }
-        self.program_ptr = program_ptr_before;
     }
 
-    fn cleanup(&mut self) {
-        // Assert that all open loops have been terminated
-        if !self.loop_stack.is_empty() {
-            let e = format!("unmatched '[' at program position(s): {:?}", self.loop_stack);
-            self.status = ExecutionStatus::ProgramError(e.to_string());
+    /// Run a line entered at the REPL against the current data, then return to the program.
+    fn run_subprogram(&mut self, line: &str) {
+        let program = match Program::compile(line) {
+            Ok(program) => program,
+            Err(e) => return eprintln!("{}", e),
         };
+        let program = mem::replace(&mut self.program, program);
+        let program_ptr = mem::replace(&mut self.program_ptr, 0);
+        let loop_stack = mem::take(&mut self.loop_stack);
+        while self.status == ExecutionStatus::InProgress {
+            match self.program.ops().get(self.program_ptr) {
+                Some(&op) => self.run_op(op),
+                None => break,
+            };
+        }
+        self.program = program;
+        self.program_ptr = program_ptr;
+        self.loop_stack = loop_stack;
+    }
+
+    fn current_cell(&mut self) -> &mut i64 {
+        self.data.get_mut(self.data_ptr)
     }
 
-    fn pointer_increment(&mut self) {
-        self.data_ptr += 1;
-        if self.data.get(self.data_ptr).is_none() {
-            self.data.push(0);
+    fn pointer_move(&mut self, n: isize) {
+        if let Some(index) = self.resolve(n) {
+            self.data_ptr = index;
         };
     }
 
-    fn pointer_decrement(&mut self) {
-        match self.data_ptr {
-            0 => self.data.insert(0, 0),
-            _ => self.data_ptr -= 1,
+    /// Index of the cell at the offset from the current cell, following the configured tape
+    /// edges, growing the tape to include it. If the cell is past an edge that stops the program or
+    /// reaching it would take the tape past the memory limit, the status is set and `None` is
+    /// returned.
+    fn resolve(&mut self, offset: isize) -> Option<isize> {
+        let index = self.resolve_edges(offset)?;
+        let limit = self.config.memory_limit.map(MemoryLimit::cells);
+        if matches!(limit, Some(limit) if self.data.len_with(index) > limit) {
+            let position = self.position();
+            self.status = ExecutionStatus::Stopped(Error::MemoryLimit { cell: index, position });
+            return None
+        };
+        if index < self.data.start() || index >= self.data.end() {
+            self.data.grow_to(index);
+            self.observer.on_tape_grow(index, self.data.len());
         };
+        Some(index)
     }
 
-    fn value_increment(&mut self) {
-        self.data[self.data_ptr] = self.data[self.data_ptr].wrapping_add(1);
+    fn resolve_edges(&mut self, offset: isize) -> Option<isize> {
+        let index = self.data_ptr + offset;
+        match self.config.tape_size {
+            Some(size) => {
+                let size = size as isize;
+                match self.config.tape_edge {
+                    _ if (0..size).contains(&index) => return Some(index),
+                    EdgePolicy::Wrap => return Some(index.rem_euclid(size)),
+                    EdgePolicy::Clamp => return Some(index.clamp(0, size - 1)),
+                    EdgePolicy::Error => {},
+                }
+            },
+            None if index < 0 && self.config.left_edge == LeftEdgePolicy::Error => {},
+            None => return Some(index),
+        };
+        let position = self.position();
+        self.status = ExecutionStatus::Stopped(Error::OffTape { cell: index, position });
+        None
     }
 
-    fn value_decrement(&mut self) {
-        self.data[self.data_ptr] = self.data[self.data_ptr].wrapping_sub(1);
+    fn value_add(&mut self, n: i128) {
+        self.value_add_at(0, n);
     }
 
-    fn put_character(&mut self) {
-        if let Some(ctx_inner) = self.ctx.iter_mut().next() {
-            (*ctx_inner).write_all(&self.data[self.data_ptr..=self.data_ptr]).unwrap();
+    /// Add to the cell at the offset from the current cell, handling overflow as configured. The
+    /// sum is taken as an `i128` so that overflow can be detected for any `CellWidth`.
+    fn value_add_at(&mut self, offset: isize, n: i128) {
+        let width = self.config.cell_width;
+        let index = match self.resolve(offset) {
+            Some(index) => index,
+            None => return,
+        };
+        let sum = i128::from(self.data.get(index)) + n;
+        let (min, max) = (i128::from(width.min()), i128::from(width.max()));
+        *self.data.get_mut(index) = match self.config.overflow {
+            // truncating to `i64` keeps the sum correct modulo the cell size
+            OverflowPolicy::Wrap => width.wrap(sum as i64),
+            OverflowPolicy::Saturate => sum.clamp(min, max) as i64,
+            OverflowPolicy::Error if sum < min || sum > max => {
+                let position = self.position();
+                // an op addressing the cell by offset stops where its folded moves would have
+                self.data_ptr = index;
+                self.status = ExecutionStatus::Stopped(Error::Overflow { cell: index, position });
+                return
+            },
+            OverflowPolicy::Error => sum as i64,
         };
     }
 
-    fn get_character(&mut self) {
-        if let Some(ctx_inner) = self.ctx.iter_mut().next() {
-            let mut buffer: [u8; 1] = [0; 1];
-            match (*ctx_inner).read(&mut buffer[..]) {
-                Ok(n) if n == 1 => self.data[self.data_ptr] = buffer[0],
-                // do nothing if we got nothing -- note that this decision is an important
-                // contributor towards program behavior
-                Ok(_) => {},
-                Err(e) => self.status = ExecutionStatus::InternalError(format!("{}", e).to_string()),
-            };
+    fn multiply_add(&mut self, offset: isize, factor: i64) {
+        let value = self.data.get(self.data_ptr);
+        if value != 0 {
+            self.value_add_at(offset, i128::from(value) * i128::from(factor));
         };
     }
 
-    fn find_loop_end(ptr: usize, program: &[Token]) -> Result<usize, ()> {
-        match program.get(ptr) {
-            Some(Token::LoopEnd) => Ok(ptr),
-            Some(Token::LoopBeg) => ExecutionContext::find_loop_end(ptr + 1, program)
-                .and_then(|i| ExecutionContext::find_loop_end(i + 1, program)),
-            Some(_) => ExecutionContext::find_loop_end(ptr + 1, program),
-            None => Err(()),
+    /// Move by a single step of the scan, so that every cell passed counts towards the budget.
+    fn scan(&mut self, n: isize) {
+        match self.data.get(self.data_ptr) {
+            0 => self.program_ptr += 1,
+            _ => self.pointer_move(n),
         }
     }
 
-    fn loop_enter(&mut self) {
-        match self.data[self.data_ptr] {
-            0 => match ExecutionContext::find_loop_end(self.program_ptr + 1, &self.program) {
-                Ok(i) => self.program_ptr = i,
-                Err(_) => {
-                    let e = format!(
-                        "'[' at program position {} missing corresponding ']'", self.program_ptr
-                    );
-                    self.status = ExecutionStatus::ProgramError(e.to_string());
-                },
+    fn put_character(&mut self, offset: isize) {
+        let index = match self.resolve(offset) {
+            Some(index) => index,
+            None => return,
+        };
+        let value = self.data.get(index);
+        // only the low byte is written, whatever the cell width
+        match self.write_byte(value as u8) {
+            Ok(()) => self.observer.on_output(value as u8),
+            Err(e) => {
+                // an op addressing the cell by offset stops where its folded moves would have
+                self.data_ptr = index;
+                self.status = ExecutionStatus::Stopped(Error::io(e, Some(self.position())));
+            },
+        };
+    }
+
+    /// Write a byte of output, as `Write::write_all` would.
+    fn write_byte(&mut self, byte: u8) -> io::Result<()> {
+        loop {
+            match self.ctx.write_output(&[byte]) {
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::WriteZero)),
+                Ok(_) => return Ok(()),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(e) => return Err(e),
+            };
+        }
+    }
+
+    /// Flush the output once the program has finished, so that a failing write is not lost.
+    fn flush(&mut self) -> io::Result<()> {
+        self.ctx.flush_output()
+    }
+
+    fn get_character(&mut self) {
+        let mut buffer: [u8; 1] = [0; 1];
+        match self.ctx.read_input(&mut buffer[..]) {
+            Ok(1) => {
+                self.observer.on_input(buffer[0]);
+                *self.current_cell() = i64::from(buffer[0]);
+            },
+            // what to do if we got nothing is an important contributor towards program
+            // behavior, so it is left to the config
+            Ok(_) => self.end_of_input(),
+            Err(e) => {
+                self.status = ExecutionStatus::Stopped(Error::io(e, Some(self.position())));
+            },
+        };
+    }
+
+    fn end_of_input(&mut self) {
+        let width = self.config.cell_width;
+        match self.config.eof {
+            EofPolicy::Unchanged => {},
+            EofPolicy::Zero => *self.current_cell() = 0,
+            EofPolicy::AllOnes => *self.current_cell() = width.wrap(-1),
+            EofPolicy::Error => {
+                let position = self.position();
+                self.status = ExecutionStatus::Stopped(Error::InputExhausted { position });
+            },
+        };
+    }
+
+    fn loop_enter(&mut self, target: usize) {
+        match self.data.get(self.data_ptr) {
+            0 => self.program_ptr = target,
+            _ => {
+                self.observer.on_loop_enter(self.program_ptr);
+                self.loop_stack.push(self.program_ptr);
+                self.program_ptr += 1;
             },
-            _ => self.loop_stack.push(self.program_ptr),
         }
     }
 
-    fn loop_exit(&mut self) {
-        match (self.loop_stack.pop(), self.data[self.data_ptr]) {
-            (Some(_), 0) => self.program_ptr += 1,
-            (Some(ptr_loc), _) => self.program_ptr = ptr_loc,
-            (None, _) => {
-                let e = format!(
-                    "']' at program position {} missing corresponding '['", self.program_ptr
-                );
-                self.status = ExecutionStatus::ProgramError(e.to_string());
+    fn loop_exit(&mut self, target: usize) {
+        match self.data.get(self.data_ptr) {
+            0 => {
+                self.observer.on_loop_exit(self.program_ptr);
+                self.loop_stack.pop();
+                self.program_ptr += 1;
             },
+            _ => self.program_ptr = target,
         }
     }
 }
@@ -235,63 +698,504 @@ impl<'a> ExecutionContext<'a> {
 #[cfg(test)]
 mod test {
     use super::*;
-    use std::cell::RefCell;
-    use crate::ioctx::{InMemoryIoCtx, IoCtx};
+    use std::thread;
+    use std::time::Duration;
+    use crate::config::CellWidth;
+    use crate::ioctx::{InMemoryIoCtx, IoCtx, StdIoCtx};
+    use crate::token::Span;
+
+    type TestContext = ExecutionContext<InMemoryIoCtx>;
 
     #[test]
     fn test_pointer_increment() {
-        let mut ectx = ExecutionContext::default();
-        ectx.pointer_increment();
+        let mut ectx = TestContext::default();
+        ectx.pointer_move(1);
         assert_eq!(1, ectx.data_ptr);
-        assert_eq!(vec![0, 0], ectx.data);
+        assert_eq!(vec![0, 0], ectx.data.to_vec());
     }
 
     #[test]
     fn test_pointer_decrement() {
-        let mut ectx = ExecutionContext::default();
-        ectx.pointer_decrement();
-        assert_eq!(0, ectx.data_ptr);
-        assert_eq!(vec![0, 0], ectx.data);
+        let mut ectx = TestContext::default();
+        ectx.pointer_move(-1);
+        assert_eq!(-1, ectx.data_ptr);
+        assert_eq!(vec![0, 0], ectx.data.to_vec());
+    }
+
+    #[test]
+    fn test_pointer_move() {
+        let mut ectx = TestContext::default();
+        ectx.pointer_move(3);
+        ectx.value_add(7);
+        ectx.pointer_move(-5);
+        assert_eq!(-2, ectx.data_ptr);
+        assert_eq!(vec![0, 0, 0, 0, 0, 7], ectx.data.to_vec());
+    }
+
+    #[test]
+    fn test_value_add_wraps() {
+        let mut ectx = TestContext::default();
+        ectx.value_add(-3);
+        assert_eq!(vec![253], ectx.data.to_vec());
+        ectx.value_add(259);
+        assert_eq!(vec![0], ectx.data.to_vec());
+    }
+
+    #[test]
+    fn test_cell_widths() {
+        for &(width, expected) in &[
+            (CellWidth::U8, 255),
+            (CellWidth::U16, 65535),
+            (CellWidth::U32, 4294967295),
+            (CellWidth::I64, -1),
+        ] {
+            let mut ectx = TestContext::default();
+            ectx.config.cell_width = width;
+            ectx.value_add(-1);
+            assert_eq!(vec![expected], ectx.data.to_vec());
+            ectx.value_add(1);
+            assert_eq!(vec![0], ectx.data.to_vec());
+        };
+    }
+
+    #[test]
+    fn test_overflow_policies() {
+        for &(width, policy, expected_high, expected_low) in &[
+            (CellWidth::U8, OverflowPolicy::Wrap, 4, 253),
+            (CellWidth::U8, OverflowPolicy::Saturate, 255, 0),
+            (CellWidth::I64, OverflowPolicy::Saturate, i64::MAX, i64::MIN),
+        ] {
+            let mut ectx = ExecutionContext {
+                config: Config { cell_width: width, overflow: policy, ..Config::default() },
+                ..TestContext::default()
+            };
+            ectx.value_add(i128::from(width.max()) - 2);
+            ectx.value_add(7);
+            assert_eq!(vec![expected_high], ectx.data.to_vec());
+            ectx.value_add(-i128::from(expected_high) + i128::from(width.min()) - 3);
+            assert_eq!(vec![expected_low], ectx.data.to_vec());
+        };
+    }
+
+    #[test]
+    fn test_overflow_error() {
+        let config = Config { overflow: OverflowPolicy::Error, ..Config::default() };
+        let mut ectx = ExecutionContext::with_config(InMemoryIoCtx::default(), "+>\n->-", config);
+        let span = Some(Span { offset: 3, line: 2, column: 1 });
+        let e = Error::Overflow { cell: 1, position: Position { op: 1, span } };
+        assert_eq!(e.to_string(), "cell 1 overflowed at program position 1 (line 2, column 1)");
+        assert_eq!(ectx.execute(), ExecutionStatus::Stopped(e));
+    }
+
+    #[test]
+    fn test_tape_edges() {
+        for (edge, expected_ptr, expected_data) in [
+            (EdgePolicy::Wrap, 2, vec![1, 5, 0]),
+            (EdgePolicy::Clamp, 2, vec![4, 0, 2]),
+        ] {
+            let mut ectx = ExecutionContext {
+                config: Config { tape_size: Some(3), tape_edge: edge, ..Config::default() },
+                ..TestContext::default()
+            };
+            ectx.load_program("+>>>>++<<<+++>>>>");
+            assert_eq!(ectx.execute(), ExecutionStatus::Terminated);
+            assert_eq!((expected_ptr, expected_data), (ectx.data_ptr, ectx.data.to_vec()));
+        };
+    }
+
+    #[test]
+    fn test_tape_edge_errors() {
+        let bounded = Config { tape_size: Some(2), ..Config::default() };
+        let left = Config { left_edge: LeftEdgePolicy::Error, ..Config::default() };
+        for (config, program, expected_cell, expected_op) in [
+            (bounded.clone(), "+>+>+", 2, 3),
+            (bounded, "[-]<", -1, 1),
+            (left, "+[<]", -1, 1),
+        ] {
+            let mut ectx = ExecutionContext { config, ..TestContext::default() };
+            ectx.load_program(program);
+            match ectx.execute() {
+                ExecutionStatus::Stopped(Error::OffTape { cell, position }) => {
+                    assert_eq!((expected_cell, expected_op), (cell, position.op));
+                },
+                status => panic!("{:?}", status),
+            };
+        };
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let config = Config { tape_size: Some(0), ..Config::default() };
+        let mut ectx = ExecutionContext::with_config(InMemoryIoCtx::default(), "+", config);
+        match ectx.execute() {
+            ExecutionStatus::Stopped(Error::InvalidConfig(_)) => {},
+            status => panic!("{:?}", status),
+        };
+    }
+
+    #[test]
+    fn test_step() {
+        let program = Program::compile("+[-]").unwrap();
+        let mut ectx = ExecutionContext { program, ..TestContext::default() };
+        assert_eq!(ectx.step(), ExecutionStatus::InProgress);
+        assert_eq!((1, vec![1]), (ectx.steps(), ectx.data.to_vec()));
+        assert_eq!(ectx.step_n(2), ExecutionStatus::InProgress);
+        assert_eq!((3, vec![0]), (ectx.steps(), ectx.data.to_vec()));
+        assert_eq!(ectx.step_n(10), ExecutionStatus::Terminated);
+        assert_eq!(ectx.step(), ExecutionStatus::Terminated);
+        assert_eq!(4, ectx.steps());
+    }
+
+    #[test]
+    fn test_run_until() {
+        let program = Program::compile("+++[>+<-]").unwrap();
+        let mut ectx = ExecutionContext { program, ..TestContext::default() };
+        assert_eq!(ectx.run_until(|e| e.data.get(1) == 2), ExecutionStatus::InProgress);
+        assert_eq!(vec![2, 2], ectx.data.to_vec());
+        assert_eq!(ectx.run_until(|_| false), ExecutionStatus::Terminated);
+        assert_eq!(vec![0, 3], ectx.data.to_vec());
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let program = Program::compile("++[>+<-]").unwrap();
+        let mut ectx = ExecutionContext { program, ..TestContext::default() };
+        ectx.step_n(4);
+        assert_eq!((1, 4), (ectx.data_ptr(), ectx.program_ptr()));
+        assert_eq!(&[1], ectx.loop_stack());
+        let snapshot = ectx.snapshot();
+        assert_eq!(snapshot, Snapshot {
+            tape: ectx.tape().clone(),
+            data_ptr: 1,
+            program_ptr: 4,
+            loop_stack: vec![1],
+            steps: 4,
+            status: ExecutionStatus::InProgress,
+        });
+        assert_eq!(vec![2, 1], snapshot.tape.to_vec());
+        ectx.execute();
+        assert_eq!(vec![0, 2], ectx.tape().to_vec());
+        assert!(ectx.loop_stack().is_empty());
+    }
+
+    #[test]
+    fn test_save_restore() {
+        let program = ",>,[<+>-]<.";
+        let mut ictx = InMemoryIoCtx::default();
+        ictx.write_input(b"\x03\x04").unwrap();
+        let mut ectx = ExecutionContext::new(&mut ictx, program);
+        // the second byte of input is still pending
+        ectx.step_n(2);
+        let saved = ectx.save();
+        let status = ectx.execute();
+        let snapshot = ectx.snapshot();
+        drop(ectx);
+
+        let mut restored_ictx = InMemoryIoCtx::default();
+        let (mut restored, input) = ExecutionContext::restore(&mut restored_ictx, &saved).unwrap();
+        assert_eq!(input, Some(vec![4]));
+        restored.get_mut().write_input(&input.unwrap()).unwrap();
+        assert_eq!(restored.steps(), 2);
+        assert_eq!(restored.execute(), status);
+        assert_eq!(restored.snapshot(), snapshot);
+        drop(restored);
+        let mut buf = [0u8; 2];
+        assert_eq!(restored_ictx.read_output(&mut buf).unwrap(), 1);
+        assert_eq!(buf[0], 7);
+    }
+
+    #[test]
+    fn test_restore_invalid() {
+        let mut ictx = InMemoryIoCtx::default();
+        let mut saved = ExecutionContext::new(&mut ictx, "+[-]").save();
+        assert!(ExecutionContext::restore(&mut ictx, &saved[..saved.len() - 1]).is_err());
+        saved.push(0);
+        assert!(ExecutionContext::restore(&mut ictx, &saved).is_err());
+        assert!(ExecutionContext::restore(&mut ictx, b"+[-]").is_err());
+
+        let mut ectx = ExecutionContext::new(InMemoryIoCtx::default(), "+");
+        *ectx.data.get_mut(0) = 300;
+        match ExecutionContext::restore(&mut ictx, &ectx.save()) {
+            Err(Error::InvalidSave(_)) => {},
+            result => panic!("{:?}", result.map(|(ectx, _)| ectx.status)),
+        };
+        let config = Config { tape_size: Some(2), ..Config::default() };
+        let mut ectx = ExecutionContext::with_config(InMemoryIoCtx::default(), "+", config);
+        ectx.data.grow_to(2);
+        ectx.data_ptr = 2;
+        match ExecutionContext::restore(&mut ictx, &ectx.save()) {
+            Err(Error::InvalidSave(_)) => {},
+            result => panic!("{:?}", result.map(|(ectx, _)| ectx.status)),
+        };
+        let config = Config { memory_limit: Some(MemoryLimit::Cells(2)), ..Config::default() };
+        let mut ectx = ExecutionContext::with_config(InMemoryIoCtx::default(), "+", config);
+        ectx.data.grow_to(2);
+        match ExecutionContext::restore(&mut ictx, &ectx.save()) {
+            Err(Error::InvalidSave(_)) => {},
+            result => panic!("{:?}", result.map(|(ectx, _)| ectx.status)),
+        };
+    }
+
+    #[test]
+    fn test_restore_pending_input() {
+        let mut ictx = InMemoryIoCtx::default();
+        ictx.write_input(b"ab").unwrap();
+        let mut ectx = ExecutionContext::new(&mut ictx, ",.,.,.,.");
+        ectx.step_n(2);
+        let saved = ectx.save();
+        drop(ectx);
+
+        // the saved input is handed back, even to a context that cannot take it
+        let (_, input) = ExecutionContext::restore(StdIoCtx::default(), &saved).unwrap();
+        assert_eq!(input, Some(b"b".to_vec()));
+        // which leaves it to the caller to choose where it goes among any other input
+        let mut restored_ictx = InMemoryIoCtx::default();
+        let (mut restored, input) = ExecutionContext::restore(&mut restored_ictx, &saved).unwrap();
+        restored.get_mut().write_input(&input.unwrap()).unwrap();
+        restored.get_mut().write_input(b"XY").unwrap();
+        restored.execute();
+        drop(restored);
+        let mut buf = [0u8; 4];
+        assert_eq!(restored_ictx.read_output(&mut buf).unwrap(), 3);
+        assert_eq!(&buf[..3], b"bXY");
+    }
+
+    #[test]
+    fn test_optimized_state() {
+        let error = Config { overflow: OverflowPolicy::Error, ..Config::default() };
+        let wrap = Config {
+            tape_size: Some(5),
+            tape_edge: crate::config::EdgePolicy::Wrap,
+            ..Config::default()
+        };
+        let cases = [
+            (">>->+", &error),
+            ("<<>>>-<+", &error),
+            ("+>>.<<<-", &error),
+            ("<><<+-", &wrap),
+        ];
+        for &(source, config) in cases.iter() {
+            let mut optimized = ExecutionContext {
+                config: config.clone(),
+                ..TestContext::default()
+            };
+            optimized.load_program(source);
+            let program = Program::compile(source).unwrap();
+            let config = config.clone();
+            let mut ectx = ExecutionContext { config, program, ..TestContext::default() };
+            // the program stops on the same cell as the original, leaving the same tape, though
+            // at a different position in the optimized program
+            let stopped_on = |status: ExecutionStatus| match status {
+                ExecutionStatus::Stopped(Error::Overflow { cell, position }) => {
+                    Some((cell, position.span))
+                },
+                status => {
+                    assert_eq!(status, ExecutionStatus::Terminated);
+                    None
+                },
+            };
+            assert_eq!(stopped_on(optimized.execute()), stopped_on(ectx.execute()), "{}", source);
+            assert_eq!((optimized.data_ptr, optimized.tape()), (ectx.data_ptr, ectx.tape()));
+        }
+    }
+
+    #[test]
+    fn test_max_steps() {
+        let config = Config { max_steps: Some(10), ..Config::default() };
+        let mut ectx = ExecutionContext { config, ..TestContext::default() };
+        ectx.load_program("+[]");
+        assert_eq!(ectx.execute(), ExecutionStatus::Stopped(Error::StepLimit { steps: 10 }));
+        assert_eq!(10, ectx.steps());
+        // a scan counts every cell it passes
+        let config = Config { max_steps: Some(10), ..Config::default() };
+        let mut ectx = ExecutionContext { config, ..TestContext::default() };
+        ectx.load_program(&format!("{}<<<<<<<[>]", "+>".repeat(8)));
+        assert_eq!(ectx.execute(), ExecutionStatus::Stopped(Error::StepLimit { steps: 10 }));
+    }
+
+    #[test]
+    fn test_timeout() {
+        let config = Config { timeout: Some(Duration::from_millis(10)), ..Config::default() };
+        let mut ectx = ExecutionContext { config, ..TestContext::default() };
+        ectx.load_program("+[]");
+        match ectx.execute() {
+            ExecutionStatus::Stopped(Error::Timeout { steps }) => assert_eq!(steps, ectx.steps()),
+            status => panic!("{:?}", status),
+        };
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        let memory_limit = Some(MemoryLimit::Cells(4));
+        let mut ectx = ExecutionContext {
+            config: Config { memory_limit, ..Config::default() },
+            ..TestContext::default()
+        };
+        ectx.load_program("<+>>>+[>+]");
+        match ectx.execute() {
+            ExecutionStatus::Stopped(Error::MemoryLimit { cell, .. }) => assert_eq!(cell, 3),
+            status => panic!("{:?}", status),
+        };
+        assert_eq!((2, vec![1, 0, 0, 1]), (ectx.data_ptr, ectx.data.to_vec()));
     }
 
     #[test]
-    fn test_find_loop_end() {
-        let program = vec![Token::PtrInc, Token::LoopEnd];
-        assert_eq!(Ok(1), ExecutionContext::find_loop_end(0, &program));
+    fn test_cancellation() {
+        let token = CancellationToken::new();
+        let mut ectx = TestContext::default();
+        ectx.set_cancellation_token(token.clone());
+        ectx.load_program("+[]");
+        let canceller = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            token.cancel();
+        });
+        assert_eq!(ectx.execute(), ExecutionStatus::Stopped(Error::Cancelled));
+        canceller.join().unwrap();
+    }
+
+    struct ClosedIoCtx;
+
+    impl IoCtx for ClosedIoCtx {
+        fn read_input(&mut self, _: &mut [u8]) -> io::Result<usize> { Ok(0) }
+        fn write_output(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::BrokenPipe, "closed"))
+        }
+        fn flush_output(&mut self) -> io::Result<()> { Ok(()) }
+    }
+
+    #[test]
+    fn test_output_error() {
+        let mut ectx = ExecutionContext::new(ClosedIoCtx, "+.+.");
+        match ectx.execute() {
+            ExecutionStatus::Stopped(Error::Io { kind, position: Some(position), .. }) => {
+                assert_eq!((kind, position.op), (io::ErrorKind::BrokenPipe, 1));
+            },
+            status => panic!("{:?}", status),
+        };
+        assert_eq!(vec![1], ectx.data.to_vec());
+    }
+
+    #[test]
+    fn test_wide_cell_output() {
+        let config = Config { cell_width: CellWidth::U16, ..Config::default() };
+        // 0x141 is output as its low byte, 'A'
+        let program = format!("{}[>{}<-]>+.", "+".repeat(16), "+".repeat(20));
+        let mut ectx = ExecutionContext::with_config(InMemoryIoCtx::default(), &program, config);
+        assert_eq!(ectx.execute(), ExecutionStatus::Terminated);
+        assert_eq!(vec![0, 0x141], ectx.data.to_vec());
+        let mut ictx = ectx.into_inner();
+        let mut buf = [0u8; 2];
+        assert_eq!(ictx.read_output(&mut buf).unwrap(), 1);
+        assert_eq!(b'A', buf[0]);
+    }
+
+    #[test]
+    fn test_send() {
+        fn assert_send<T: Send>(_: &T) {}
+        let mut ectx = ExecutionContext::new(InMemoryIoCtx::default(), "+[-]");
+        assert_send(&ectx);
+        let status = thread::spawn(move || ectx.execute()).join().unwrap();
+        assert_eq!(status, ExecutionStatus::Terminated);
+    }
+
+    #[test]
+    fn test_deeply_nested_loops() {
+        let depth = 100_000;
+        let program = format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        let mut ectx = TestContext::default();
+        ectx.load_program(&program);
+        assert_eq!(ectx.execute(), ExecutionStatus::Terminated);
     }
 
     #[test]
     fn test_input_output() {
-        let ictx = RefCell::new(Box::new(InMemoryIoCtx::default()) as Box<dyn IoCtx>);
-        let mut ictx_ref = ictx.borrow_mut();
+        let mut ictx = InMemoryIoCtx::default();
         let val = b"value";
-        ictx_ref.write_input(val).unwrap();
-        let status = ExecutionContext::new(ictx_ref, ",[.[-],]").execute();
+        ictx.write_input(val).unwrap();
+        let status = ExecutionContext::new(&mut ictx, ",[.[-],]").execute();
         let mut buf = [0u8; 5];
-        let output = ictx.borrow_mut().read_output(&mut buf);
+        let output = ictx.read_output(&mut buf);
         assert_eq!(output.unwrap(), 5usize);
         assert_eq!(val, &buf);
-        assert_eq!(status, ExecutionStatus::<String>::Terminated);
+        assert_eq!(status, ExecutionStatus::Terminated);
+    }
+
+    #[test]
+    fn test_eof_policies() {
+        for &(width, policy, expected) in &[
+            (CellWidth::U8, EofPolicy::Unchanged, 7),
+            (CellWidth::U8, EofPolicy::Zero, 0),
+            (CellWidth::U8, EofPolicy::AllOnes, 255),
+            (CellWidth::I64, EofPolicy::AllOnes, -1),
+        ] {
+            let config = Config { cell_width: width, eof: policy, ..Config::default() };
+            let ictx = InMemoryIoCtx::default();
+            let mut ectx = ExecutionContext::with_config(ictx, "+++++++,", config);
+            assert_eq!(ectx.execute(), ExecutionStatus::Terminated);
+            assert_eq!(vec![expected], ectx.data.to_vec());
+        };
+    }
+
+    #[test]
+    fn test_eof_error() {
+        let mut ictx = InMemoryIoCtx::default();
+        ictx.write_input(b"a").unwrap();
+        let config = Config { eof: EofPolicy::Error, ..Config::default() };
+        let mut ectx = ExecutionContext::with_config(&mut ictx, ",.,.", config);
+        let span = Some(Span { offset: 2, line: 1, column: 3 });
+        let e = Error::InputExhausted { position: Position { op: 2, span } };
+        assert_eq!(ectx.execute(), ExecutionStatus::Stopped(e));
     }
 
     #[test]
     fn test_missing_close_bracket() {
-        for token in vec![Token::LoopBeg, Token::LoopEnd] {
-            let mut ectx = ExecutionContext::default();
-            ectx.program = vec![token];
+        for program in &["[", "]"] {
+            let mut ectx = TestContext::default();
+            ectx.load_program(program);
             let status = ectx.execute();
             match status {
-                ExecutionStatus::ProgramError(_) => {},
+                ExecutionStatus::Stopped(Error::UnmatchedOpen(_))
+                | ExecutionStatus::Stopped(Error::UnmatchedClose(_)) => {},
                 _ => panic!(),
             };
         };
     }
 
+    #[test]
+    fn test_unreached_bracket_rejected_before_execution() {
+        let mut ectx = TestContext::default();
+        ectx.load_program("+[-]]");
+        match ectx.status {
+            ExecutionStatus::Stopped(Error::UnmatchedClose(_)) => {},
+            _ => panic!(),
+        };
+        assert_eq!(vec![0], ectx.data.to_vec());
+    }
+
+    #[test]
+    fn test_error_source_location() {
+        let mut ectx = TestContext::default();
+        ectx.load_program("+\n+[-");
+        let e = "unmatched '[' at line 2, column 2\n  +[-\n   ^";
+        match ectx.execute() {
+            ExecutionStatus::Stopped(error) => assert_eq!(error.to_string(), e),
+            status => panic!("{:?}", status),
+        };
+    }
+
+    #[test]
+    fn test_debug_fmt_location() {
+        let mut ectx = TestContext::default();
+        ectx.load_program("+\n  >#");
+        ectx.program_ptr = 2;
+        assert!(format!("{:?}", ectx).contains("program_ptr: 2 (line 2, column 4)\n"));
+    }
+
     #[test]
     fn test_debug_fmt() {
-        let mut ectx = ExecutionContext::default();
-        ectx.program = vec![Token::DebugDump];
+        let mut ectx = TestContext::default();
+        ectx.load_program("#");
         let status = ectx.execute();
-        assert_eq!(status, ExecutionStatus::<String>::Terminated);
+        assert_eq!(status, ExecutionStatus::Terminated);
     }
 }
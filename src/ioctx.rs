@@ -52,6 +52,34 @@ pub trait IoCtx {
     fn write_input(&mut self, _: &[u8]) -> io::Result<usize> {
         panic!("`write_input` unsupported for `StdIoCtx`");
     }
+
+    /// Copy of the input that has been written but not yet read, if the `IoCtx` can tell.
+    ///
+    /// The default implementation returns `None`, as input streams like stdin cannot be looked
+    /// ahead into.
+    fn pending_input(&mut self) -> Option<Vec<u8>> {
+        None
+    }
+}
+
+/// Lets an `ExecutionContext` borrow an I/O context rather than own it.
+impl<I: IoCtx + ?Sized> IoCtx for &mut I {
+    fn read_input(&mut self, buf: &mut [u8]) -> io::Result<usize> { (**self).read_input(buf) }
+    fn write_output(&mut self, buf: &[u8]) -> io::Result<usize> { (**self).write_output(buf) }
+    fn flush_output(&mut self) -> io::Result<()> { (**self).flush_output() }
+    fn read_output(&mut self, buf: &mut [u8]) -> io::Result<usize> { (**self).read_output(buf) }
+    fn write_input(&mut self, buf: &[u8]) -> io::Result<usize> { (**self).write_input(buf) }
+    fn pending_input(&mut self) -> Option<Vec<u8>> { (**self).pending_input() }
+}
+
+/// Lets an `ExecutionContext` own an I/O context picked at runtime, e.g. a `Box<dyn IoCtx>`.
+impl<I: IoCtx + ?Sized> IoCtx for Box<I> {
+    fn read_input(&mut self, buf: &mut [u8]) -> io::Result<usize> { (**self).read_input(buf) }
+    fn write_output(&mut self, buf: &[u8]) -> io::Result<usize> { (**self).write_output(buf) }
+    fn flush_output(&mut self) -> io::Result<()> { (**self).flush_output() }
+    fn read_output(&mut self, buf: &mut [u8]) -> io::Result<usize> { (**self).read_output(buf) }
+    fn write_input(&mut self, buf: &[u8]) -> io::Result<usize> { (**self).write_input(buf) }
+    fn pending_input(&mut self) -> Option<Vec<u8>> { (**self).pending_input() }
 }
 
 impl Read for dyn IoCtx {
@@ -91,10 +119,8 @@ impl IoCtx for StdIoCtx {
 
 
 /// `StdIoCtx` that flushes the output stream on every call to `write_output`.
+#[derive(Default)]
 pub struct UnbufferedStdIoCtx { ctx: StdIoCtx }
-impl Default for UnbufferedStdIoCtx {
-    fn default() -> Self { Self { ctx: StdIoCtx::default() } }
-}
 
 /// # Panics
 ///
@@ -112,14 +138,11 @@ impl IoCtx for UnbufferedStdIoCtx {
 
 
 /// Struct wrapper for u8 vector implementing `Read`, `Write` traits
+#[derive(Default)]
 struct ByteBuf {
     buf: Vec<u8>,
 }
 
-impl Default for ByteBuf {
-    fn default() -> Self { Self { buf: Vec::new() } }
-}
-
 impl Read for ByteBuf {
     fn read(&mut self, input_buf: &mut [u8]) -> io::Result<usize> {
         // slice of input buffer for which Read is implemented
@@ -140,24 +163,44 @@ impl Write for ByteBuf {
 /// individually support both `Read`, `Write` for use when program output is intended to be
 /// consumed by the process executing it, rather than a separate program or a human, both of which
 /// are better served by the `StdIoCtx`.
+#[derive(Default)]
 pub struct InMemoryIoCtx {
     input: ByteBuf,
     output: ByteBuf,
 }
 
-impl Default for InMemoryIoCtx {
-    fn default() -> Self {
-        Self {
-            input: ByteBuf::default(),
-            output: ByteBuf::default(),
-        }
-    }
-}
-
 impl IoCtx for InMemoryIoCtx {
     fn read_input(&mut self, buf: &mut [u8]) -> io::Result<usize> { self.input.read(buf) }
     fn write_input(&mut self, buf: &[u8]) -> io::Result<usize> { self.input.write(buf) }
     fn write_output(&mut self, buf: &[u8]) -> io::Result<usize> { self.output.write(buf) }
     fn read_output(&mut self, buf: &mut [u8]) -> io::Result<usize> { self.output.read(buf) }
     fn flush_output(&mut self) -> io::Result<()> { self.output.flush() }
+    fn pending_input(&mut self) -> Option<Vec<u8>> { Some(self.input.buf.clone()) }
+}
+
+
+/// `IoCtx` reading input from any `Read` and handing every byte of output to a callback as soon
+/// as it is written, so that output is neither held in memory nor held back until the end.
+pub struct StreamIoCtx<R: Read, F: FnMut(&[u8])> {
+    input: R,
+    output: F,
+}
+
+impl<R: Read, F: FnMut(&[u8])> StreamIoCtx<R, F> {
+    pub fn new(input: R, output: F) -> Self {
+        Self { input, output }
+    }
+}
+
+/// # Panics
+///
+/// The default implementations for `write_input` and `read_output` are used here which panic
+/// unconditionally.
+impl<R: Read, F: FnMut(&[u8])> IoCtx for StreamIoCtx<R, F> {
+    fn read_input(&mut self, buf: &mut [u8]) -> io::Result<usize> { self.input.read(buf) }
+    fn write_output(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (self.output)(buf);
+        Ok(buf.len())
+    }
+    fn flush_output(&mut self) -> io::Result<()> { Ok(()) }
 }
@@ -0,0 +1,164 @@
+//! Intermediate representation of programs, as executed by the interpreter.
+
+use crate::check;
+use crate::error::Error;
+use crate::token::{Span, Token};
+
+
+/// A single operation of a program. Runs of identical `Token`s are folded into one operation.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Op {
+    /// Add to the current cell, from a run of `+` (positive) or `-` (negative).
+    Add(i64),
+
+    /// Move the data pointer, from a run of `>` (positive) or `<` (negative).
+    Move(isize),
+
+    /// If the current cell is zero, continue at the given position (just past the matching
+    /// `JumpIfNonZero`).
+    JumpIfZero(usize),
+
+    /// If the current cell is not zero, continue at the given position (just past the matching
+    /// `JumpIfZero`).
+    JumpIfNonZero(usize),
+
+    In,
+    Out,
+    DebugDump,
+    DebugBreakpoint,
+
+    /// Set the current cell to zero, from a `[-]` or `[+]` loop.
+    Clear,
+
+    /// If the current cell is not zero, add it multiplied by the factor to the cell at the offset
+    /// from the current cell, from the body of a loop like `[->++<]`.
+    MulAdd(isize, i64),
+
+    /// Move the data pointer by the given step until the current cell is zero, from a loop like
+    /// `[>]`.
+    Scan(isize),
+
+    /// Add to the cell at the offset from the current cell, without moving the data pointer.
+    AddAt(isize, i64),
+
+    /// Output the cell at the offset from the current cell, without moving the data pointer.
+    OutAt(isize),
+}
+
+
+/// A program lowered into `Op`s, ready to be executed.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Program {
+    pub(crate) ops: Vec<Op>,
+
+    /// Location in the source of each op. For folded ops, this is the first `Token` of the run
+    /// and for ops replacing a loop, it is the loop's `[`.
+    pub(crate) spans: Vec<Span>,
+}
+
+
+impl Program {
+    /// Parse, check and lower a program, returning the first bracket error found in the source
+    /// otherwise.
+    pub fn compile(source: &str) -> Result<Self, Error> {
+        let tokens = Token::parse_spanned(source);
+        if let Err(mut diagnostics) = check::check_tokens(source, &tokens) {
+            return Err(Error::from(diagnostics.remove(0)));
+        };
+        Ok(Program::lower(&tokens))
+    }
+
+    /// Lower a checked token stream, folding runs and resolving the target of every jump.
+    /// Assumes that the brackets are balanced.
+    fn lower(tokens: &[(Token, Span)]) -> Self {
+        let mut program = Program::default();
+        let mut open: Vec<usize> = Vec::new();
+        for &(token, span) in tokens {
+            // only runs of the same token are folded, `+-` stays as two ops
+            let folded = match (program.ops.last_mut(), token) {
+                (Some(Op::Add(n)), Token::ValInc) if *n > 0 => { *n += 1; true },
+                (Some(Op::Add(n)), Token::ValDec) if *n < 0 => { *n -= 1; true },
+                (Some(Op::Move(n)), Token::PtrInc) if *n > 0 => { *n += 1; true },
+                (Some(Op::Move(n)), Token::PtrDec) if *n < 0 => { *n -= 1; true },
+                _ => false,
+            };
+            if folded {
+                continue;
+            };
+            let op = match token {
+                Token::ValInc => Op::Add(1),
+                Token::ValDec => Op::Add(-1),
+                Token::PtrInc => Op::Move(1),
+                Token::PtrDec => Op::Move(-1),
+                Token::PutChar => Op::Out,
+                Token::GetChar => Op::In,
+                Token::LoopBeg => {
+                    open.push(program.ops.len());
+                    Op::JumpIfZero(0)  // target filled in when the matching ']' is reached
+                },
+                Token::LoopEnd => {
+                    let i = program.ops.len();
+                    let j = open.pop().unwrap_or_default();
+                    program.ops[j] = Op::JumpIfZero(i + 1);
+                    Op::JumpIfNonZero(j + 1)
+                },
+                Token::DebugDump => Op::DebugDump,
+                Token::DebugBreakpoint => Op::DebugBreakpoint,
+            };
+            program.ops.push(op);
+            program.spans.push(span);
+        }
+        program
+    }
+
+    /// The operations making up the program.
+    pub fn ops(&self) -> &[Op] {
+        &self.ops
+    }
+
+    /// Location in the source of each op, see `ops`.
+    pub fn spans(&self) -> &[Span] {
+        &self.spans
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_folding() {
+        let program = Program::compile("+++--c>>o<m+-").unwrap();
+        assert_eq!(program.ops(), &[
+            Op::Add(3), Op::Add(-2), Op::Move(2), Op::Move(-1), Op::Add(1), Op::Add(-1),
+        ]);
+        let columns: Vec<usize> = program.spans().iter().map(|s| s.column).collect();
+        assert_eq!(columns, vec![1, 4, 7, 10, 12, 13]);
+    }
+
+    #[test]
+    fn test_jump_targets() {
+        let program = Program::compile("[>[-]<]").unwrap();
+        assert_eq!(program.ops(), &[
+            Op::JumpIfZero(7),
+            Op::Move(1),
+            Op::JumpIfZero(5),
+            Op::Add(-1),
+            Op::JumpIfNonZero(3),
+            Op::Move(-1),
+            Op::JumpIfNonZero(1),
+        ]);
+    }
+
+    #[test]
+    fn test_debug_ops() {
+        let program = Program::compile("##%").unwrap();
+        assert_eq!(program.ops(), &[Op::DebugDump, Op::DebugDump, Op::DebugBreakpoint]);
+    }
+
+    #[test]
+    fn test_unmatched() {
+        assert!(Program::compile("[[]").is_err());
+    }
+}
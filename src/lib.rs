@@ -3,32 +3,44 @@
 
 extern crate libc;
 
-use std::cell::RefCell;
 use std::ffi::CStr;
+use std::io::Read;
 use std::mem;
+use std::ptr;
 use std::slice;
+use std::time::Duration;
 
-use libc::{c_char, size_t, c_uchar};
+use libc::{c_char, c_void, size_t, c_uchar};
 
-use ioctx::{IoCtx, InMemoryIoCtx};
-use interpreter::{ExecutionStatus, ExecutionContext};
+use cancel::CancellationToken;
+use config::{
+    CellWidth, Config, EdgePolicy, EofPolicy, LeftEdgePolicy, MemoryLimit, OverflowPolicy,
+};
+use ioctx::{IoCtx, InMemoryIoCtx, StreamIoCtx};
+use interpreter::ExecutionStatus;
+use output::OutputBytes;
+use tape::Tape;
 
 
+pub mod builder;
+pub mod cancel;
+pub mod check;
+pub mod config;
+pub mod error;
 pub mod ioctx;
 pub mod interpreter;
+pub mod ir;
+pub mod observer;
+pub mod optimize;
+pub mod output;
+pub mod tape;
 pub mod token;
 mod repl;
+mod save;
 
-
-/// The different ways the execution of a program can fail.
-#[derive(Debug)]
-pub enum Error<T> {
-    /// A 'you' problem.
-    ProgramError(T),
-
-    /// A 'me' problem.
-    InternalError(T),
-}
+pub use builder::Interpreter;
+pub use check::check;
+pub use error::{Error, Position};
 
 
 /// Execute a program using the `bfi` interpreter. The output of the program (as placed by the `.`
@@ -49,28 +61,262 @@ pub enum Error<T> {
 pub fn execute(
     program: &str,
     input: &[u8],
-) -> Result<Vec<u8>, Error<String>>
+) -> Result<Vec<u8>, Error>
+{
+    execute_with_config(program, input, &Config::default())
+}
+
+
+/// Execute a program like `execute`, using the options in `config`.
+///
+/// # Examples
+///
+/// ```rust
+/// extern crate bfi;
+///
+/// use bfi::config::{CellWidth, Config};
+///
+/// fn main () {
+///     let config = Config { cell_width: CellWidth::U16, ..Config::default() };
+///     // 256 wraps to 0 in a u8 cell, so only wider cells see a non-zero value and output 1
+///     let program = "++++++++++++++++[>++++++++++++++++<-]>[<+>[-]]<.";
+///     assert_eq!(bfi::execute_with_config(program, b"", &config).unwrap(), vec![1]);
+/// }
+/// ```
+pub fn execute_with_config(
+    program: &str,
+    input: &[u8],
+    config: &Config,
+) -> Result<Vec<u8>, Error>
+{
+    execute_with_report(program, input, config).into_result()
+}
+
+
+/// Execute a program like `execute_with_config`, stopping early with `Error::Cancelled` if the
+/// token is cancelled from another thread.
+///
+/// # Examples
+///
+/// ```rust
+/// extern crate bfi;
+///
+/// use std::thread;
+/// use std::time::Duration;
+/// use bfi::cancel::CancellationToken;
+/// use bfi::config::Config;
+///
+/// fn main () {
+///     let token = CancellationToken::new();
+///     let handle = token.clone();
+///     thread::spawn(move || {
+///         thread::sleep(Duration::from_millis(10));
+///         handle.cancel();
+///     });
+///     match bfi::execute_cancellable("+[]", b"", &Config::default(), &token) {
+///         Err(bfi::Error::Cancelled) => println!("stopped"),
+///         _ => unreachable!(),
+///     };
+/// }
+/// ```
+pub fn execute_cancellable(
+    program: &str,
+    input: &[u8],
+    config: &Config,
+    token: &CancellationToken,
+) -> Result<Vec<u8>, Error>
+{
+    report(program.as_bytes(), input, config, Some(token)).into_result()
+}
+
+
+/// Execute a program like `execute_with_config`, reading input from `input` as the program asks
+/// for it and passing output to `output` as soon as it is written rather than collecting it.
+///
+/// # Examples
+///
+/// ```rust
+/// extern crate bfi;
+///
+/// use bfi::config::{Config, EofPolicy};
+///
+/// fn main () {
+///     let mut lines = 0;
+///     let program = ",[.,]";
+///     let input: &[u8] = b"one\ntwo\n";
+///     let config = Config { eof: EofPolicy::Zero, ..Config::default() };
+///     bfi::execute_streaming(program, input, &config, |bytes| {
+///         lines += bytes.iter().filter(|&&b| b == b'\n').count();
+///     }).unwrap();
+///     assert_eq!(lines, 2);
+/// }
+/// ```
+pub fn execute_streaming<R, F>(
+    program: &str,
+    input: R,
+    config: &Config,
+    output: F,
+) -> Result<(), Error>
+where
+    R: Read,
+    F: FnMut(&[u8]),
 {
-    let ictx = RefCell::new(Box::new(InMemoryIoCtx::default()) as Box<dyn IoCtx>);
-    let mut ictx_ref = ictx.borrow_mut();
-    if ictx_ref.write_input(&input[..]).is_err() {
-        return Err(Error::InternalError("unable to open buffer".to_string()));
+    let mut ectx = Interpreter::builder()
+        .program(program)
+        .io(StreamIoCtx::new(input, output))
+        .config(config.clone())
+        .build()?;
+    match ectx.execute() {
+        ExecutionStatus::Stopped(e) => Err(e),
+        _ => Ok(()),
+    }
+}
+
+
+/// Execute a program like `execute_with_config`, lazily: the program only runs as far as needed
+/// to produce each byte taken from the returned iterator, see `output::OutputBytes`.
+///
+/// # Examples
+///
+/// ```rust
+/// extern crate bfi;
+///
+/// use bfi::config::Config;
+///
+/// fn main () {
+///     // counts up forever, but only the first three bytes are ever computed
+///     let output = bfi::execute_iter("+[.+]", b"", &Config::default());
+///     let first: Result<Vec<u8>, bfi::Error> = output.take(3).collect();
+///     assert_eq!(first.unwrap(), vec![1, 2, 3]);
+/// }
+/// ```
+pub fn execute_iter(program: &str, input: &[u8], config: &Config) -> OutputBytes {
+    let mut ictx = InMemoryIoCtx::default();
+    if let Err(e) = ictx.write_input(input) {
+        return OutputBytes::failed(Error::io(e, None));
     };
-    let status = ExecutionContext::new(ictx_ref, program).execute();
-    let mut ictx_ref = ictx.borrow_mut();
-    match status {
-        ExecutionStatus::Terminated => {
-            let mut output: Vec<u8> = Vec::new();
-            let mut buf: [u8; 256] = [0; 256];
-            while let Ok(n) = ictx_ref.read_output(&mut buf) {
-                if n == 0 { break };
-                output.extend_from_slice(&buf[..n]);
-            };
-            Ok(output)
-        },
-        ExecutionStatus::ProgramError(e) => Err(Error::ProgramError(e)),
-        ExecutionStatus::InternalError(e) => Err(Error::InternalError(e)),
-        _ => Err(Error::InternalError("unknown error occurred".to_string())),
+    match Interpreter::builder().program(program).io(ictx).config(config.clone()).build() {
+        Ok(ectx) => OutputBytes::new(ectx),
+        Err(e) => OutputBytes::failed(e),
+    }
+}
+
+
+/// Everything there is to know about a finished execution, see `execute_with_report`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExecutionReport {
+    /// Output written by the program, up to where it stopped.
+    pub output: Vec<u8>,
+
+    /// The tape as the program left it.
+    pub tape: Tape,
+
+    /// Index on the tape of the cell the program ended on.
+    pub data_ptr: isize,
+
+    /// Number of steps executed.
+    pub steps: u64,
+
+    /// Number of cells between the leftmost and the rightmost cell reached.
+    pub max_extent: usize,
+
+    /// Number of bytes of the input read by the program.
+    pub input_consumed: usize,
+
+    /// Status the program stopped with.
+    pub status: ExecutionStatus,
+}
+
+
+impl ExecutionReport {
+    /// Report on an execution that could not be started.
+    fn failed(e: Error) -> Self {
+        let tape = Tape::default();
+        ExecutionReport {
+            output: Vec::new(),
+            max_extent: tape.len(),
+            tape,
+            data_ptr: 0,
+            steps: 0,
+            input_consumed: 0,
+            status: ExecutionStatus::Stopped(e),
+        }
+    }
+
+    /// Reduce the report to the output of the program if it terminated without errors, or the
+    /// `Error` it stopped with, as returned by `execute_with_config`. A report on a program that
+    /// has not stopped yet reduces to `Error::Unfinished`.
+    pub fn into_result(self) -> Result<Vec<u8>, Error> {
+        match self.status {
+            ExecutionStatus::Terminated => Ok(self.output),
+            ExecutionStatus::Stopped(e) => Err(e),
+            ExecutionStatus::NotStarted | ExecutionStatus::InProgress => Err(Error::Unfinished),
+        }
+    }
+}
+
+
+/// Execute a program like `execute_with_config`, reporting on the execution however it ends
+/// rather than only returning the output.
+///
+/// # Examples
+///
+/// ```rust
+/// extern crate bfi;
+///
+/// use bfi::config::Config;
+/// use bfi::interpreter::ExecutionStatus;
+///
+/// fn main () {
+///     let report = bfi::execute_with_report(",.>,.<<", b"ab", &Config::default());
+///     assert_eq!(report.output, b"ab");
+///     assert_eq!(report.input_consumed, 2);
+///     assert_eq!((report.data_ptr, report.max_extent), (-1, 3));
+///     assert_eq!(report.status, ExecutionStatus::Terminated);
+/// }
+/// ```
+pub fn execute_with_report(program: &str, input: &[u8], config: &Config) -> ExecutionReport {
+    report(program.as_bytes(), input, config, None)
+}
+
+
+fn report(
+    program: &[u8],
+    input: &[u8],
+    config: &Config,
+    token: Option<&CancellationToken>,
+) -> ExecutionReport
+{
+    let mut ictx = InMemoryIoCtx::default();
+    if let Err(e) = ictx.write_input(input) {
+        return ExecutionReport::failed(Error::io(e, None));
+    };
+    let mut builder = Interpreter::builder().program_bytes(program).io(ictx).config(config.clone());
+    if let Some(token) = token {
+        builder = builder.cancellation_token(token.clone());
+    };
+    let mut ectx = match builder.build() {
+        Ok(ectx) => ectx,
+        Err(e) => return ExecutionReport::failed(e),
+    };
+    let status = ectx.execute();
+    let snapshot = ectx.snapshot();
+    let mut ictx = ectx.into_inner();
+    let mut output: Vec<u8> = Vec::new();
+    let mut buf: [u8; 256] = [0; 256];
+    while let Ok(n) = ictx.read_output(&mut buf) {
+        if n == 0 { break };
+        output.extend_from_slice(&buf[..n]);
+    };
+    let pending = ictx.pending_input().map_or(0, |pending| pending.len());
+    ExecutionReport {
+        output,
+        max_extent: snapshot.tape.len(),
+        tape: snapshot.tape,
+        data_ptr: snapshot.data_ptr,
+        steps: snapshot.steps,
+        input_consumed: input.len() - pending,
+        status,
     }
 }
 
@@ -98,6 +344,192 @@ impl BfExecResult {
             output_length: 0,
         }
     }
+
+    fn from_output(output: Result<Vec<u8>, Error>) -> Self {
+        match output {
+            Ok(mut v) => {
+                // ensure v.len() == v.capacity() such that the capacity of the vector does not
+                // need to be shared with the foreign caller in order for the subsequent call to
+                // `bf_free` to not leak -- both len and capacity are required when calling
+                // `Vec::from_raw_parts`
+                v.shrink_to_fit();
+                let l = v.len();
+                let ptr = v.as_mut_ptr();
+                // instruct rust to forget about this section of memory -- it will only be
+                // deallocated if the vector is reassembled and dropped (see `bf_free`)
+                mem::forget(v);
+                BfExecResult {
+                    success: 1,
+                    output: ptr,
+                    output_length: l,
+                }
+            },
+            // point to garbage -- will certainly crash the program if this location is returned
+            // to `bf_free`, so it is up to the foreign caller to be responsible here (as always)
+            Err(_) => BfExecResult::default_failure(),
+        }
+    }
+}
+
+
+/// Options for `bf_exec_with_options`, the foreign-friendly counterpart of `config::Config`.
+///
+/// Start from the defaults returned by `bf_default_options` and change only the fields needed.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BfExecOptions {
+    /// Number of bits in each cell, one of 8, 16, 32 or 64 (see `config::CellWidth`).
+    pub cell_size: c_uchar, // u8
+
+    /// Overflow policy, one of 0 (wrap), 1 (saturate) or 2 (error), see
+    /// `config::OverflowPolicy`.
+    pub overflow: c_uchar, // u8
+
+    /// Number of cells on a bounded tape, or 0 for an unbounded tape.
+    pub tape_size: size_t, // usize
+
+    /// Behavior at the ends of a bounded tape, one of 0 (error), 1 (wrap) or 2 (clamp), see
+    /// `config::EdgePolicy`.
+    pub tape_edge: c_uchar, // u8
+
+    /// Behavior to the left of the starting cell of an unbounded tape, one of 0 (grow) or
+    /// 1 (error), see `config::LeftEdgePolicy`.
+    pub left_edge: c_uchar, // u8
+
+    /// Behavior of `,` once the input is exhausted, one of 0 (unchanged), 1 (zero), 2 (all ones)
+    /// or 3 (error), see `config::EofPolicy`.
+    pub eof: c_uchar, // u8
+
+    /// Largest number of cells the tape may grow to, or 0 for no limit.
+    pub max_cells: size_t, // usize
+}
+
+impl BfExecOptions {
+    /// Convert into a `Config`, if all options are valid.
+    fn to_config(self) -> Option<Config> {
+        Some(Config {
+            cell_width: CellWidth::from_bits(u32::from(self.cell_size))?,
+            overflow: match self.overflow {
+                0 => OverflowPolicy::Wrap,
+                1 => OverflowPolicy::Saturate,
+                2 => OverflowPolicy::Error,
+                _ => return None,
+            },
+            tape_size: match self.tape_size {
+                0 => None,
+                n => Some(n),
+            },
+            tape_edge: match self.tape_edge {
+                0 => EdgePolicy::Error,
+                1 => EdgePolicy::Wrap,
+                2 => EdgePolicy::Clamp,
+                _ => return None,
+            },
+            left_edge: match self.left_edge {
+                0 => LeftEdgePolicy::Grow,
+                1 => LeftEdgePolicy::Error,
+                _ => return None,
+            },
+            eof: match self.eof {
+                0 => EofPolicy::Unchanged,
+                1 => EofPolicy::Zero,
+                2 => EofPolicy::AllOnes,
+                3 => EofPolicy::Error,
+                _ => return None,
+            },
+            memory_limit: match self.max_cells {
+                0 => None,
+                n => Some(MemoryLimit::Cells(n)),
+            },
+            // limits are only set by `bf_exec_limited`
+            ..Config::default()
+        })
+    }
+}
+
+
+/// Execution budget for `bf_exec_limited`, the foreign-friendly counterpart of the limits in
+/// `config::Config`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BfExecLimits {
+    /// Number of steps after which execution is stopped, or 0 for no limit.
+    pub max_steps: u64,
+
+    /// Wall-clock time in milliseconds after which execution is stopped, or 0 for no limit.
+    pub timeout_ms: u64,
+}
+
+impl BfExecLimits {
+    /// Apply the limits to a `Config`.
+    fn apply(self, config: &mut Config) {
+        config.max_steps = match self.max_steps {
+            0 => None,
+            n => Some(n),
+        };
+        config.timeout = match self.timeout_ms {
+            0 => None,
+            ms => Some(Duration::from_millis(ms)),
+        };
+    }
+}
+
+
+/// Result of a call to `bf_exec_limited`, adding how far execution got to a `BfExecResult`.
+#[repr(C)]
+pub struct BfExecLimitedResult {
+    /// Output of the program, to be freed with `bf_free` on success as for `bf_exec`.
+    pub result: BfExecResult,
+
+    /// Boolean (0 or 1) indicating that execution was stopped because the budget ran out.
+    pub exhausted: c_uchar, // u8
+
+    /// Boolean (0 or 1) indicating that execution was stopped by `bf_cancel`.
+    pub cancelled: c_uchar, // u8
+
+    /// Number of steps executed.
+    pub steps: u64,
+}
+
+
+/// Callback receiving output from `bf_exec_streaming`, with the `user_data` pointer passed to it
+/// and `length` bytes of output starting at `output`. The output is only valid during the call.
+pub type BfOutputCallback = extern "C" fn(
+    user_data: *mut c_void,
+    output: *const c_uchar,
+    length: size_t,
+);
+
+
+/// Result of a call to `bf_exec_streaming`, which has no output to return or free.
+#[repr(C)]
+pub struct BfExecStreamResult {
+    /// Boolean (0 or 1) indicating that the program terminated without errors.
+    pub success: c_uchar, // u8
+
+    /// Boolean (0 or 1) indicating that execution was stopped because the budget ran out.
+    pub exhausted: c_uchar, // u8
+
+    /// Boolean (0 or 1) indicating that execution was stopped by `bf_cancel`.
+    pub cancelled: c_uchar, // u8
+
+    /// Number of steps executed.
+    pub steps: u64,
+}
+
+
+/// Get the default options used by `bf_exec`, as a starting point for `bf_exec_with_options`.
+#[no_mangle]
+pub extern "C" fn bf_default_options() -> BfExecOptions {
+    BfExecOptions {
+        cell_size: 8,
+        overflow: 0,
+        tape_size: 0,
+        tape_edge: 0,
+        left_edge: 0,
+        eof: 0,
+        max_cells: 0,
+    }
 }
 
 
@@ -116,38 +548,201 @@ pub unsafe extern "C" fn bf_exec(
     input_length: size_t,
 ) -> BfExecResult
 {
-    let program_str: &str = match CStr::from_ptr(program).to_str() { // unsafe
-        Ok(s) => s,
-        // return failure if the program provided is not valid unicode
-        Err(_) => return BfExecResult::default_failure(),
+    bf_exec_with_options(program, input, input_length, bf_default_options()) // unsafe
+}
+
+
+/// Interface to `bfi::execute_with_config` a program from foreign code. Fails without running the
+/// program if any of the `options` are invalid.
+///
+/// # Safety
+///
+/// See `bf_exec`.
+#[no_mangle]
+pub unsafe extern "C" fn bf_exec_with_options(
+    program: *const c_char,
+    input: *const c_uchar,
+    input_length: size_t,
+    options: BfExecOptions,
+) -> BfExecResult
+{
+    // a program that is not valid unicode fails to build
+    let program_bytes: &[u8] = CStr::from_ptr(program).to_bytes(); // unsafe
+
+    let input_slice: &[u8] = slice::from_raw_parts(input, input_length); //unsafe
+
+    match options.to_config() {
+        Some(config) => BfExecResult::from_output(
+            report(program_bytes, input_slice, &config, None).into_result()
+        ),
+        None => BfExecResult::default_failure(),
+    }
+}
+
+
+/// Interface to `bfi::execute_with_config` a program from foreign code like
+/// `bf_exec_with_options`, stopping execution once the step or time budget in `limits` runs out.
+///
+/// # Safety
+///
+/// See `bf_exec`.
+#[no_mangle]
+pub unsafe extern "C" fn bf_exec_limited(
+    program: *const c_char,
+    input: *const c_uchar,
+    input_length: size_t,
+    options: BfExecOptions,
+    limits: BfExecLimits,
+) -> BfExecLimitedResult
+{
+    bf_exec_cancellable(program, input, input_length, options, limits, ptr::null()) // unsafe
+}
+
+
+/// Interface to `bfi::execute_cancellable` a program from foreign code like `bf_exec_limited`,
+/// stopping execution once `bf_cancel` is called with the `cancel` handle from another thread.
+/// The handle may be null for a program that cannot be cancelled.
+///
+/// # Safety
+///
+/// See `bf_exec`. The `cancel` handle must be null or returned by `bf_cancel_new` and not yet
+/// freed.
+#[no_mangle]
+pub unsafe extern "C" fn bf_exec_cancellable(
+    program: *const c_char,
+    input: *const c_uchar,
+    input_length: size_t,
+    options: BfExecOptions,
+    limits: BfExecLimits,
+    cancel: *const CancellationToken,
+) -> BfExecLimitedResult
+{
+    let failure = BfExecLimitedResult {
+        result: BfExecResult::default_failure(),
+        exhausted: 0,
+        cancelled: 0,
+        steps: 0,
     };
+    let program_bytes: &[u8] = CStr::from_ptr(program).to_bytes(); // unsafe
 
-    let input_slice: &[u8] = slice::from_raw_parts(input, input_length as usize); //unsafe
+    let input_slice: &[u8] = slice::from_raw_parts(input, input_length); //unsafe
 
-    match execute(program_str, input_slice) {
-        Ok(mut v) => {
-            // ensure v.len() == v.capacity() such that the capacity of the vector does not need to
-            // be shared with the foreign caller in order for the subsequent call to `bf_free` to
-            // not leak -- both len and capacity are required when calling `Vec::from_raw_parts`
-            v.shrink_to_fit();
-            let l = v.len();
-            let ptr = v.as_mut_ptr();
-            // instruct rust to forget about this section of memory -- it will only be
-            // deallocated if the vector is reassembled and dropped (see `bf_free`)
-            mem::forget(v);
-            BfExecResult {
-                success: 1,
-                output: ptr,
-                output_length: l,
-            }
-        },
-        // point to garbage -- will certainly crash the program if this location is returned to
-        // `bf_free`, so it is up to the foreign caller to be responsible here (as always)
-        Err(_) => BfExecResult::default_failure(),
+    let mut config = match options.to_config() {
+        Some(config) => config,
+        None => return failure,
+    };
+    limits.apply(&mut config);
+    let token = cancel.as_ref(); // unsafe
+    let report = report(program_bytes, input_slice, &config, token);
+    BfExecLimitedResult {
+        exhausted: matches!(
+            report.status,
+            ExecutionStatus::Stopped(Error::StepLimit { .. })
+            | ExecutionStatus::Stopped(Error::Timeout { .. })
+        ) as c_uchar,
+        cancelled: (report.status == ExecutionStatus::Stopped(Error::Cancelled)) as c_uchar,
+        steps: report.steps,
+        result: BfExecResult::from_output(report.into_result()),
     }
 }
 
 
+/// Interface to `bfi::execute_streaming` a program from foreign code, otherwise like
+/// `bf_exec_cancellable`. Output is passed to `callback` as it is written, together with
+/// `user_data`, instead of being returned. A null `callback` discards the output.
+///
+/// # Safety
+///
+/// See `bf_exec_cancellable`. `callback` is called on the calling thread, before this function
+/// returns, and must not keep the output pointer it is given.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn bf_exec_streaming(
+    program: *const c_char,
+    input: *const c_uchar,
+    input_length: size_t,
+    options: BfExecOptions,
+    limits: BfExecLimits,
+    cancel: *const CancellationToken,
+    callback: Option<BfOutputCallback>,
+    user_data: *mut c_void,
+) -> BfExecStreamResult
+{
+    let mut result = BfExecStreamResult { success: 0, exhausted: 0, cancelled: 0, steps: 0 };
+    let program_bytes: &[u8] = CStr::from_ptr(program).to_bytes(); // unsafe
+
+    let input_slice: &[u8] = slice::from_raw_parts(input, input_length); //unsafe
+
+    let mut config = match options.to_config() {
+        Some(config) => config,
+        None => return result,
+    };
+    limits.apply(&mut config);
+    let output = |bytes: &[u8]| {
+        if let Some(callback) = callback {
+            callback(user_data, bytes.as_ptr(), bytes.len());
+        };
+    };
+    let mut builder = Interpreter::builder()
+        .program_bytes(program_bytes)
+        .io(StreamIoCtx::new(input_slice, output))
+        .config(config);
+    if let Some(token) = cancel.as_ref() { // unsafe
+        builder = builder.cancellation_token(token.clone());
+    };
+    let mut ectx = match builder.build() {
+        Ok(ectx) => ectx,
+        Err(_) => return result,
+    };
+    let status = ectx.execute();
+    result.success = (status == ExecutionStatus::Terminated) as c_uchar;
+    result.exhausted = matches!(
+        status,
+        ExecutionStatus::Stopped(Error::StepLimit { .. })
+        | ExecutionStatus::Stopped(Error::Timeout { .. })
+    ) as c_uchar;
+    result.cancelled = (status == ExecutionStatus::Stopped(Error::Cancelled)) as c_uchar;
+    result.steps = ectx.steps();
+    result
+}
+
+
+/// Create a handle to stop a call to `bf_exec_cancellable` from another thread. The handle must
+/// be freed with `bf_cancel_free` once the call has returned.
+#[no_mangle]
+pub extern "C" fn bf_cancel_new() -> *mut CancellationToken {
+    Box::into_raw(Box::new(CancellationToken::new()))
+}
+
+
+/// Stop every call to `bf_exec_cancellable` using the handle. Safe to call from any thread, and
+/// more than once.
+///
+/// # Safety
+///
+/// The handle must have been returned by `bf_cancel_new` and not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn bf_cancel(cancel: *const CancellationToken) {
+    if let Some(token) = cancel.as_ref() { // unsafe
+        token.cancel();
+    };
+}
+
+
+/// Free a handle returned by `bf_cancel_new`.
+///
+/// # Safety
+///
+/// The handle must have been returned by `bf_cancel_new`, not already be freed and no longer be
+/// in use by a call to `bf_exec_cancellable`.
+#[no_mangle]
+pub unsafe extern "C" fn bf_cancel_free(cancel: *mut CancellationToken) {
+    if !cancel.is_null() {
+        drop(Box::from_raw(cancel)); // unsafe
+    };
+}
+
+
 /// Deallocate the memory containing the output of a previous call to `bf_exec`.
 ///
 /// The output returned from `bf_exec` represents a vector in memory that has been forgotten by Rust
@@ -169,7 +764,7 @@ pub unsafe extern "C" fn bf_free(
     to_free: *mut c_uchar,
     length: size_t,
 ) {
-    Vec::from_raw_parts(to_free as *mut u8, length as usize, length as usize);
+    Vec::from_raw_parts(to_free, length, length);
 }
 
 
@@ -187,11 +782,35 @@ mod test {
             for b in 0..=255 {
                 let output: Vec<u8> = execute(ADD_PROGRAM, &[a, b]).unwrap();
                 let expected_output: u8 = a.wrapping_add(b);
-                assert_eq!(output.iter().nth(0).unwrap(), &expected_output);
+                assert_eq!(output.first().unwrap(), &expected_output);
             };
         };
     }
 
+    #[test]
+    fn test_report() {
+        let report = execute_with_report(",.,.[-]+", b"a", &Config {
+            eof: EofPolicy::Error,
+            ..Config::default()
+        });
+        assert_eq!(report.output, b"a");
+        assert_eq!((report.input_consumed, report.steps), (1, 3));
+        assert_eq!(report.tape.to_vec(), vec![97]);
+        match report.status {
+            ExecutionStatus::Stopped(Error::InputExhausted { position }) => {
+                assert_eq!(position.op, 2);
+            },
+            status => panic!("{:?}", status),
+        };
+        let report = execute_with_report("+>>>+<<", b"ab", &Config::default());
+        assert_eq!(report.input_consumed, 0);
+        assert_eq!((report.data_ptr, report.max_extent), (1, 4));
+        assert_eq!(report.clone().into_result().unwrap(), Vec::<u8>::new());
+        assert_eq!(report.status, ExecutionStatus::Terminated);
+        let report = ExecutionReport { status: ExecutionStatus::InProgress, ..report };
+        assert_eq!(report.into_result(), Err(Error::Unfinished));
+    }
+
     #[test]
     fn test_foreign_addition() {
         let program: *const c_char = CString::new(ADD_PROGRAM).unwrap().into_raw();
@@ -221,6 +840,187 @@ mod test {
         assert_eq!(result.success, 0u8);
     }
 
+    #[test]
+    fn test_foreign_options() {
+        let program = b"-.\x00";
+        let program_ptr = program.as_ptr() as *const c_char;
+        let mut options = bf_default_options();
+        options.cell_size = 16;
+        let result = unsafe { bf_exec_with_options(program_ptr, [].as_ptr(), 0, options) };
+        assert_eq!(result.success, 1u8);
+        let output: &[u8] = unsafe { slice::from_raw_parts(result.output, result.output_length) };
+        assert_eq!(output, &[255]);
+        unsafe { bf_free(result.output, result.output_length) };
+
+        options.cell_size = 12;
+        let result = unsafe { bf_exec_with_options(program_ptr, [].as_ptr(), 0, options) };
+        assert_eq!(result.success, 0u8);
+    }
+
+    #[test]
+    fn test_foreign_overflow() {
+        let program = b"-.\x00";
+        let program_ptr = program.as_ptr() as *const c_char;
+        let mut options = bf_default_options();
+        options.overflow = 2;
+        let result = unsafe { bf_exec_with_options(program_ptr, [].as_ptr(), 0, options) };
+        assert_eq!(result.success, 0u8);
+        options.overflow = 1;
+        let result = unsafe { bf_exec_with_options(program_ptr, [].as_ptr(), 0, options) };
+        assert_eq!(result.success, 1u8);
+        let output: &[u8] = unsafe { slice::from_raw_parts(result.output, result.output_length) };
+        assert_eq!(output, &[0]);
+        unsafe { bf_free(result.output, result.output_length) };
+    }
+
+    #[test]
+    fn test_foreign_tape_edges() {
+        let program = b"+<.\x00";
+        let program_ptr = program.as_ptr() as *const c_char;
+        let mut options = bf_default_options();
+        options.left_edge = 1;
+        let result = unsafe { bf_exec_with_options(program_ptr, [].as_ptr(), 0, options) };
+        assert_eq!(result.success, 0u8);
+        options.tape_size = 2;
+        options.tape_edge = 1;
+        let result = unsafe { bf_exec_with_options(program_ptr, [].as_ptr(), 0, options) };
+        assert_eq!(result.success, 1u8);
+        let output: &[u8] = unsafe { slice::from_raw_parts(result.output, result.output_length) };
+        assert_eq!(output, &[0]);
+        unsafe { bf_free(result.output, result.output_length) };
+        options.tape_edge = 3;
+        let result = unsafe { bf_exec_with_options(program_ptr, [].as_ptr(), 0, options) };
+        assert_eq!(result.success, 0u8);
+    }
+
+    #[test]
+    fn test_foreign_eof() {
+        let program = b"+,.\x00";
+        let program_ptr = program.as_ptr() as *const c_char;
+        let mut options = bf_default_options();
+        options.eof = 2;
+        let result = unsafe { bf_exec_with_options(program_ptr, [].as_ptr(), 0, options) };
+        assert_eq!(result.success, 1u8);
+        let output: &[u8] = unsafe { slice::from_raw_parts(result.output, result.output_length) };
+        assert_eq!(output, &[255]);
+        unsafe { bf_free(result.output, result.output_length) };
+        options.eof = 3;
+        let result = unsafe { bf_exec_with_options(program_ptr, [].as_ptr(), 0, options) };
+        assert_eq!(result.success, 0u8);
+    }
+
+    #[test]
+    fn test_foreign_memory_limit() {
+        let program = b"+[>+]\x00";
+        let program_ptr = program.as_ptr() as *const c_char;
+        let mut options = bf_default_options();
+        options.max_cells = 1000;
+        let result = unsafe { bf_exec_with_options(program_ptr, [].as_ptr(), 0, options) };
+        assert_eq!(result.success, 0u8);
+    }
+
+    #[test]
+    fn test_foreign_limits() {
+        let program = b"+[]\x00";
+        let program_ptr = program.as_ptr() as *const c_char;
+        let limits = BfExecLimits { max_steps: 100, timeout_ms: 0 };
+        let result = unsafe {
+            bf_exec_limited(program_ptr, [].as_ptr(), 0, bf_default_options(), limits)
+        };
+        assert_eq!((result.result.success, result.exhausted, result.steps), (0u8, 1u8, 100));
+        let limits = BfExecLimits { max_steps: 0, timeout_ms: 10 };
+        let result = unsafe {
+            bf_exec_limited(program_ptr, [].as_ptr(), 0, bf_default_options(), limits)
+        };
+        assert_eq!((result.result.success, result.exhausted), (0u8, 1u8));
+        let program = b"+.\x00";
+        let program_ptr = program.as_ptr() as *const c_char;
+        let result = unsafe {
+            bf_exec_limited(program_ptr, [].as_ptr(), 0, bf_default_options(), limits)
+        };
+        assert_eq!((result.result.success, result.exhausted, result.steps), (1u8, 0u8, 2));
+        unsafe { bf_free(result.result.output, result.result.output_length) };
+    }
+
+    #[test]
+    fn test_foreign_cancel() {
+        let program = b"+[]\x00";
+        let program_ptr = program.as_ptr() as usize;
+        let cancel = bf_cancel_new();
+        let cancel_ptr = cancel as usize;
+        let runner = std::thread::spawn(move || unsafe {
+            let limits = BfExecLimits { max_steps: 0, timeout_ms: 0 };
+            bf_exec_cancellable(
+                program_ptr as *const c_char, [].as_ptr(), 0, bf_default_options(), limits,
+                cancel_ptr as *const CancellationToken,
+            ).cancelled
+        });
+        std::thread::sleep(Duration::from_millis(10));
+        unsafe { bf_cancel(cancel) };
+        assert_eq!(runner.join().unwrap(), 1u8);
+        unsafe { bf_cancel_free(cancel) };
+    }
+
+    #[test]
+    fn test_streaming() {
+        let mut chunks: Vec<Vec<u8>> = Vec::new();
+        let input: &[u8] = b"ab";
+        let result = execute_streaming(",.,.,.", input, &Config::default(), |bytes| {
+            chunks.push(bytes.to_vec());
+        });
+        assert!(result.is_ok());
+        // the last `.` outputs the unchanged cell after the input runs out
+        assert_eq!(chunks, vec![b"a".to_vec(), b"b".to_vec(), b"b".to_vec()]);
+        let config = Config { max_steps: Some(4), ..Config::default() };
+        let mut output: Vec<u8> = Vec::new();
+        let result = execute_streaming("+[.]", input, &config, |bytes| output.extend(bytes));
+        assert_eq!(result, Err(Error::StepLimit { steps: 4 }));
+        assert_eq!(output, vec![1]);
+    }
+
+    #[test]
+    fn test_iter_build_error() {
+        let config = Config { tape_size: Some(0), ..Config::default() };
+        let mut output = execute_iter("+.", b"", &config);
+        assert!(matches!(output.next(), Some(Err(Error::InvalidConfig(_)))));
+        assert_eq!(output.next(), None);
+        assert!(output.context().is_none());
+        let mut output = execute_iter("+.[", b"", &Config::default());
+        assert!(matches!(output.next(), Some(Err(Error::UnmatchedOpen(_)))));
+        assert_eq!(output.next(), None);
+    }
+
+    extern "C" fn collect(user_data: *mut c_void, output: *const c_uchar, length: size_t) {
+        let collected = unsafe { &mut *(user_data as *mut Vec<u8>) };
+        collected.extend_from_slice(unsafe { slice::from_raw_parts(output, length) });
+    }
+
+    #[test]
+    fn test_foreign_streaming() {
+        let program = b",[.,]\x00";
+        let program_ptr = program.as_ptr() as *const c_char;
+        let mut collected: Vec<u8> = Vec::new();
+        let mut options = bf_default_options();
+        options.eof = 1;
+        let limits = BfExecLimits { max_steps: 0, timeout_ms: 0 };
+        let result = unsafe {
+            bf_exec_streaming(
+                program_ptr, b"abc".as_ptr(), 3, options, limits, ptr::null(),
+                Some(collect), &mut collected as *mut Vec<u8> as *mut c_void,
+            )
+        };
+        assert_eq!((result.success, result.exhausted), (1u8, 0u8));
+        assert_eq!(collected, b"abc");
+        let limits = BfExecLimits { max_steps: 10, timeout_ms: 0 };
+        let result = unsafe {
+            bf_exec_streaming(
+                program_ptr, b"abc".as_ptr(), 3, options, limits, ptr::null(),
+                None, ptr::null_mut(),
+            )
+        };
+        assert_eq!((result.success, result.exhausted, result.steps), (0u8, 1u8, 10));
+    }
+
     #[test]
     fn test_foreign_program_error() {
         let program = b"[";
@@ -228,4 +1028,17 @@ mod test {
         let result = unsafe { bf_exec(program_ptr, [].as_ptr(), 0) };
         assert_eq!(result.success, 0u8);
     }
+
+    #[test]
+    fn test_error_causes() {
+        match execute("+[", b"") {
+            Err(Error::UnmatchedOpen(d)) => assert_eq!(d.span.column, 2),
+            result => panic!("{:?}", result),
+        };
+        let config = Config { max_steps: Some(5), ..Config::default() };
+        match execute_with_config("+[]", b"", &config) {
+            Err(e @ Error::StepLimit { steps: 5 }) => assert!(!e.is_internal()),
+            result => panic!("{:?}", result),
+        };
+    }
 }
@@ -0,0 +1,111 @@
+//! Hooks into a running program, for tracers, profilers, coverage and the like.
+
+use crate::ir::Op;
+
+
+/// Callbacks made by an `ExecutionContext` as it executes, attached with
+/// `ExecutionContext::with_observer` or `InterpreterBuilder::observer`.
+///
+/// Every callback does nothing by default, so an observer only implements those it cares about.
+/// Positions are of operations in the compiled program, see `ExecutionContext::program`. The
+/// context is generic over its observer, so with the default `NoObserver` the calls are compiled
+/// away entirely.
+pub trait ExecutionObserver {
+    /// An operation is about to be executed.
+    fn on_op(&mut self, _position: usize, _op: Op) {}
+
+    /// The body of the loop starting at the position is being entered.
+    fn on_loop_enter(&mut self, _position: usize) {}
+
+    /// The loop ending at the position has been left, its current cell being zero.
+    fn on_loop_exit(&mut self, _position: usize) {}
+
+    /// The tape grew to `len` cells to include the cell at the index.
+    fn on_tape_grow(&mut self, _index: isize, _len: usize) {}
+
+    /// A byte of input was read.
+    fn on_input(&mut self, _byte: u8) {}
+
+    /// A byte of output was written.
+    fn on_output(&mut self, _byte: u8) {}
+}
+
+
+/// Observer that does not observe anything, the default for an `ExecutionContext`.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct NoObserver;
+
+
+impl ExecutionObserver for NoObserver {}
+
+
+/// Lets an `ExecutionContext` borrow an observer rather than own it.
+impl<O: ExecutionObserver + ?Sized> ExecutionObserver for &mut O {
+    fn on_op(&mut self, position: usize, op: Op) { (**self).on_op(position, op) }
+    fn on_loop_enter(&mut self, position: usize) { (**self).on_loop_enter(position) }
+    fn on_loop_exit(&mut self, position: usize) { (**self).on_loop_exit(position) }
+    fn on_tape_grow(&mut self, index: isize, len: usize) { (**self).on_tape_grow(index, len) }
+    fn on_input(&mut self, byte: u8) { (**self).on_input(byte) }
+    fn on_output(&mut self, byte: u8) { (**self).on_output(byte) }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::{ExecutionContext, ExecutionStatus};
+    use crate::ioctx::{InMemoryIoCtx, IoCtx};
+    use crate::ir::Program;
+
+    #[derive(Default)]
+    struct Recorder {
+        ops: Vec<usize>,
+        events: Vec<String>,
+    }
+
+    impl ExecutionObserver for Recorder {
+        fn on_op(&mut self, position: usize, _op: Op) {
+            self.ops.push(position);
+        }
+        fn on_loop_enter(&mut self, position: usize) {
+            self.events.push(format!("enter {}", position));
+        }
+        fn on_loop_exit(&mut self, position: usize) {
+            self.events.push(format!("exit {}", position));
+        }
+        fn on_tape_grow(&mut self, index: isize, len: usize) {
+            self.events.push(format!("grow {} {}", index, len));
+        }
+        fn on_input(&mut self, byte: u8) {
+            self.events.push(format!("in {}", byte));
+        }
+        fn on_output(&mut self, byte: u8) {
+            self.events.push(format!("out {}", byte));
+        }
+    }
+
+    #[test]
+    fn test_events() {
+        let mut ictx = InMemoryIoCtx::default();
+        ictx.write_input(b"\x01").unwrap();
+        let mut recorder = Recorder::default();
+        // unoptimized, so that every command is an operation
+        let program = Program::compile(",[>.<-]").unwrap();
+        let mut ectx = ExecutionContext::with_program(ictx, program).with_observer(&mut recorder);
+        assert_eq!(ectx.execute(), ExecutionStatus::Terminated);
+        drop(ectx);
+        assert_eq!(recorder.ops, vec![0, 1, 2, 3, 4, 5, 6]);
+        assert_eq!(recorder.events, vec!["in 1", "enter 1", "grow 1 2", "out 0", "exit 6"]);
+    }
+
+    #[test]
+    fn test_skipped_loop() {
+        let program = Program::compile("[-]<").unwrap();
+        let mut ectx = ExecutionContext::with_program(InMemoryIoCtx::default(), program)
+            .with_observer(Recorder::default());
+        ectx.execute();
+        let recorder = ectx.into_observer();
+        assert_eq!(recorder.ops, vec![0, 3]);
+        assert_eq!(recorder.events, vec!["grow -1 2"]);
+    }
+}
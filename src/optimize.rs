@@ -0,0 +1,379 @@
+//! Optimization passes over the intermediate representation.
+
+use std::collections::BTreeMap;
+use std::iter;
+
+use crate::config::{Config, OverflowPolicy};
+use crate::ir::{Op, Program};
+
+
+/// Optimize a program to be run with the given options by running the passes below over it, in
+/// order. Passes that would change the behavior of the program under the options are skipped.
+///
+/// The optimized program leaves the tape in exactly the same state as the original.
+pub fn optimize(program: &Program, config: &Config) -> Program {
+    // cells can only be addressed ahead of the moves reaching them if no move can stop the
+    // program at an edge of the tape or be clamped there
+    let offsets = config.moves_are_free();
+    // a replaced `[+]` or `[->++-<]` never overflows where the original loop would, while scans
+    // do not change any cells and are replaced under every policy
+    let clear = config.overflow == OverflowPolicy::Wrap;
+    let mut program = replace_idioms(program, clear, clear && offsets);
+    // a block's extent is worked out on its offsets, which do not account for moves wrapping
+    // around the ends of a bounded tape
+    if offsets && config.tape_size.is_none() {
+        program = address_offsets(&program);
+    };
+    program
+}
+
+
+/// Replace common loop idioms with single operations:
+///
+/// - Clear loops (`[-]`, `[+]`) become a `Clear`.
+/// - Balanced loops that only decrement the current cell once per iteration and add to its
+///   neighbours (`[->+<]`, `[->++>+++<<]`) become a `MulAdd` per neighbour followed by a `Clear`.
+/// - Loops that only move the pointer (`[>]`, `[<<]`) become a `Scan`.
+///
+/// Clear loops are only replaced if `clear` is set, and multiply loops only if `multiply` is.
+fn replace_idioms(program: &Program, clear: bool, multiply: bool) -> Program {
+    let mut optimized = Program::default();
+    let mut open: Vec<usize> = Vec::new();
+    for (&op, &span) in program.ops.iter().zip(program.spans.iter()) {
+        match op {
+            Op::JumpIfZero(_) => open.push(optimized.ops.len()),
+            Op::JumpIfNonZero(_) => {
+                let start = open.pop().unwrap_or_default();
+                let body = &optimized.ops[start + 1..];
+                if let Some(replacement) = replace_loop(body, clear, multiply) {
+                    let loop_span = optimized.spans[start];
+                    optimized.ops.truncate(start);
+                    optimized.spans.truncate(start);
+                    optimized.spans.extend(iter::repeat_n(loop_span, replacement.len()));
+                    optimized.ops.extend(replacement);
+                    continue;
+                };
+                // jump targets are resolved again as ops before them may have been replaced
+                let end = optimized.ops.len();
+                optimized.ops[start] = Op::JumpIfZero(end + 1);
+                optimized.ops.push(Op::JumpIfNonZero(start + 1));
+                optimized.spans.push(span);
+                continue;
+            },
+            _ => {},
+        };
+        optimized.ops.push(op);
+        optimized.spans.push(span);
+    }
+    optimized
+}
+
+
+/// Rewrite each straight-line block of `Add`, `Move` and `Out` ops into ops addressing cells by
+/// their offset from the data pointer at the start of the block, followed by a single `Move` by
+/// the net offset of the block. For example, `>+>++<<-` becomes `AddAt(1, 1)`, `AddAt(2, 2)`,
+/// `Add(-1)`.
+fn address_offsets(program: &Program) -> Program {
+    let mut optimized = Program::default();
+    let mut start = 0;
+    for (i, op) in program.ops.iter().enumerate() {
+        match op {
+            Op::Add(_) | Op::Move(_) | Op::Out => continue,
+            _ => {},
+        };
+        push_block(&mut optimized, program, start, i);
+        optimized.ops.push(*op);
+        optimized.spans.push(program.spans[i]);
+        start = i + 1;
+    }
+    push_block(&mut optimized, program, start, program.ops.len());
+    resolve_jumps(&mut optimized);
+    optimized
+}
+
+
+/// Push the block of `program` ops between `start` and `end` in offset-addressed form, keeping
+/// the block as-is if that does not make it any shorter.
+fn push_block(optimized: &mut Program, program: &Program, start: usize, end: usize) {
+    let mut ops: Vec<Op> = Vec::new();
+    let mut spans = Vec::new();
+    let mut offset: isize = 0;
+    // furthest cells the moves so far have visited and the ops pushed so far have reached, as
+    // the tape grows to the cells visited even if they are not otherwise used
+    let (mut min_offset, mut max_offset): (isize, isize) = (0, 0);
+    let (mut min_reached, mut max_reached): (isize, isize) = (0, 0);
+    for (&op, &span) in program.ops[start..end].iter().zip(program.spans[start..end].iter()) {
+        let at = match op {
+            Op::Move(n) => {
+                offset += n;
+                min_offset = min_offset.min(offset);
+                max_offset = max_offset.max(offset);
+                continue;
+            },
+            _ => offset,
+        };
+        // reach the furthest cells before any op that may stop the program, so that the tape
+        // is left as it would have been had it stopped in the original block
+        for &extreme in &[min_offset, max_offset] {
+            if extreme != at && (extreme < min_reached || extreme > max_reached) {
+                ops.push(Op::AddAt(extreme, 0));
+                spans.push(span);
+            };
+        }
+        min_reached = min_offset;
+        max_reached = max_offset;
+        ops.push(match (op, at) {
+            (op, 0) => op,
+            (Op::Add(n), _) => Op::AddAt(at, n),
+            (Op::Out, _) => Op::OutAt(at),
+            (op, _) => op,
+        });
+        spans.push(span);
+    }
+    for &extreme in &[min_offset, max_offset] {
+        if extreme != offset && (extreme < min_reached || extreme > max_reached) {
+            ops.push(Op::AddAt(extreme, 0));
+            spans.push(program.spans[start]);
+        };
+    }
+    if offset != 0 {
+        ops.push(Op::Move(offset));
+        spans.push(program.spans[start]);
+    };
+    if ops.len() < end - start {
+        optimized.ops.extend(ops);
+        optimized.spans.extend(spans);
+    } else {
+        optimized.ops.extend_from_slice(&program.ops[start..end]);
+        optimized.spans.extend_from_slice(&program.spans[start..end]);
+    };
+}
+
+
+/// Set the target of every jump from the positions of the jumps themselves.
+fn resolve_jumps(program: &mut Program) {
+    let mut open: Vec<usize> = Vec::new();
+    for i in 0..program.ops.len() {
+        match program.ops[i] {
+            Op::JumpIfZero(_) => open.push(i),
+            Op::JumpIfNonZero(_) => {
+                let start = open.pop().unwrap_or_default();
+                program.ops[start] = Op::JumpIfZero(i + 1);
+                program.ops[i] = Op::JumpIfNonZero(start + 1);
+            },
+            _ => {},
+        };
+    }
+}
+
+
+/// Find the replacement for a loop with the given body, if there is one.
+fn replace_loop(body: &[Op], clear: bool, multiply: bool) -> Option<Vec<Op>> {
+    match body {
+        [Op::Add(1)] | [Op::Add(-1)] if clear => Some(vec![Op::Clear]),
+        [Op::Move(n)] => Some(vec![Op::Scan(*n)]),
+        _ if multiply => replace_multiply_loop(body),
+        _ => None,
+    }
+}
+
+
+fn replace_multiply_loop(body: &[Op]) -> Option<Vec<Op>> {
+    let mut offset: isize = 0;
+    // every offset visited is kept, even if nothing is added to it, so that the tape grows to the
+    // same extent as it would have running the loop
+    let mut factors: BTreeMap<isize, i64> = BTreeMap::new();
+    for op in body {
+        match op {
+            Op::Add(n) => {
+                let factor = factors.entry(offset).or_insert(0);
+                *factor = factor.wrapping_add(*n);
+            },
+            Op::Move(n) => {
+                offset += n;
+                factors.entry(offset).or_insert(0);
+            },
+            _ => return None,
+        };
+    }
+    if offset != 0 || factors.get(&0) != Some(&-1) {
+        return None;
+    };
+    let mut ops: Vec<Op> = factors.into_iter()
+        .filter(|&(offset, _)| offset != 0)
+        .map(|(offset, factor)| Op::MulAdd(offset, factor))
+        .collect();
+    ops.push(Op::Clear);
+    Some(ops)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::{EdgePolicy, LeftEdgePolicy};
+    use crate::interpreter::{ExecutionContext, ExecutionStatus};
+    use crate::ioctx::{InMemoryIoCtx, IoCtx};
+    use crate::tape::Tape;
+
+    // the programs run by `tests/executable.rs`
+    const HELLO_WORLD: &str =
+        "+[-->-[>>+>-----<<]<--<---]>-.>>>+.>>..+++[.>]<<<<.+++.------.<<-.>>>>+.";
+    const HELLO_WORLD2: &str = "
+>++++++++[-<+++++++++>]<.>>+>-[+]++>++>+++[>[->+++<<
++++>]<<]>-----.>->+++..+++.>-.<<+[>[+>+]>>]<--------
+------.>>.+++.------.--------.>+.>+.";
+    const SQUARES: &str = "
+++++[>+++++<-]>[<+++++>-]+<+[
+    >[>+>+<<-]++>>[<<+>>-]>>>[-]++>[-]+
+    >>>+[[-]++++++>>>]<<<[[<++++++++<++>>-]+<.<[>----<-]<]
+    <<[>>>>>[>>>[-]+++++++++<[>-<-]+++++++++>[-[<->-]+[<<<]]<[>+<-]>]<<-]<<-
+]";
+    const CAT: &str = ",[.[-],]";
+    const SIERPINSKI: &str = "++++++++[>+>++++<<-]>++>>+<[-[>>+<<-]+>>]>+[-<<<[->[+[-]+>++
+            >>>-<<]<[<]>>++++++[<<+++++>>-]+<<++.[-]<<]>.>+[>>]>+]";
+
+    /// Run a program to completion, returning its output and the final tape and data pointer.
+    fn run(program: Program, input: &[u8]) -> (Vec<u8>, Tape, isize) {
+        let mut ictx = InMemoryIoCtx::default();
+        ictx.write_input(input).unwrap();
+        let mut ectx = ExecutionContext::with_program(ictx, program);
+        assert_eq!(ectx.execute(), ExecutionStatus::Terminated);
+        let (data, data_ptr) = (ectx.tape().clone(), ectx.data_ptr());
+        let mut ictx = ectx.into_inner();
+        let mut output: Vec<u8> = Vec::new();
+        let mut buf = [0u8; 256];
+        while let Ok(n) = ictx.read_output(&mut buf) {
+            if n == 0 { break };
+            output.extend_from_slice(&buf[..n]);
+        }
+        (output, data, data_ptr)
+    }
+
+    fn optimized(source: &str) -> Program {
+        optimize(&Program::compile(source).unwrap(), &Config::default())
+    }
+
+    #[test]
+    fn test_equivalence() {
+        let cases: [(&str, &[u8]); 5] = [
+            (HELLO_WORLD, b""),
+            (HELLO_WORLD2, b""),
+            (SQUARES, b""),
+            (CAT, b"Some testing string!\n"),
+            (SIERPINSKI, b""),
+        ];
+        for &(source, input) in cases.iter() {
+            let program = Program::compile(source).unwrap();
+            let optimized = optimize(&program, &Config::default());
+            assert_eq!(run(program, input), run(optimized, input));
+        }
+    }
+
+    #[test]
+    fn test_clear() {
+        let program = optimized("+[-]>[+]");
+        assert_eq!(program.ops(), &[Op::Add(1), Op::Clear, Op::Move(1), Op::Clear]);
+    }
+
+    #[test]
+    fn test_multiply() {
+        let program = optimized("[->++>+++<<]<[-<<+>+>]");
+        assert_eq!(program.ops(), &[
+            Op::MulAdd(1, 2),
+            Op::MulAdd(2, 3),
+            Op::Clear,
+            Op::Move(-1),
+            Op::MulAdd(-2, 1),
+            Op::MulAdd(-1, 1),
+            Op::Clear,
+        ]);
+    }
+
+    #[test]
+    fn test_scan() {
+        let program = optimized("[>>]<[<]");
+        assert_eq!(program.ops(), &[Op::Scan(2), Op::Move(-1), Op::Scan(-1)]);
+    }
+
+    #[test]
+    fn test_jump_targets() {
+        let program = optimized("[>[-]<[.>]]");
+        assert_eq!(program.ops(), &[
+            Op::JumpIfZero(9),
+            Op::Move(1),
+            Op::Clear,
+            Op::Move(-1),
+            Op::JumpIfZero(8),
+            Op::Out,
+            Op::Move(1),
+            Op::JumpIfNonZero(5),
+            Op::JumpIfNonZero(1),
+        ]);
+    }
+
+    #[test]
+    fn test_unbalanced_loops_kept() {
+        let program = Program::compile("[->+<<][-->+<][->+<.]").unwrap();
+        assert_eq!(replace_idioms(&program, true, true), program);
+    }
+
+    #[test]
+    fn test_overflow_policy() {
+        let program = Program::compile("[+]>+<[>]").unwrap();
+        let config = Config { overflow: OverflowPolicy::Saturate, ..Config::default() };
+        assert_eq!(optimize(&program, &config).ops(), &[
+            Op::JumpIfZero(3), Op::Add(1), Op::JumpIfNonZero(1), Op::AddAt(1, 1), Op::Scan(1),
+        ]);
+        let program = Program::compile("[->+<]").unwrap();
+        let config = Config { overflow: OverflowPolicy::Error, ..Config::default() };
+        assert_eq!(optimize(&program, &config).ops(), &[
+            Op::JumpIfZero(4), Op::Add(-1), Op::AddAt(1, 1), Op::JumpIfNonZero(1),
+        ]);
+    }
+
+    #[test]
+    fn test_tape_edges() {
+        let program = Program::compile("[->+<]>+<").unwrap();
+        let bounded = Config { tape_size: Some(30_000), ..Config::default() };
+        assert_eq!(optimize(&program, &bounded), program);
+        let config = Config { tape_edge: EdgePolicy::Wrap, ..bounded };
+        assert_eq!(optimize(&program, &config).ops(), &[
+            Op::MulAdd(1, 1), Op::Clear, Op::Move(1), Op::Add(1), Op::Move(-1),
+        ]);
+        let program = Program::compile("[-]>[<]").unwrap();
+        let config = Config { left_edge: LeftEdgePolicy::Error, ..Config::default() };
+        assert_eq!(optimize(&program, &config).ops(), &[Op::Clear, Op::Move(1), Op::Scan(-1)]);
+    }
+
+    #[test]
+    fn test_address_offsets() {
+        let program = optimized(">+>++<<-.>>>.<");
+        assert_eq!(program.ops(), &[
+            Op::AddAt(1, 1), Op::AddAt(2, 2), Op::Add(-1), Op::Out, Op::OutAt(3), Op::Move(2),
+        ]);
+    }
+
+    #[test]
+    fn test_address_offsets_in_loops() {
+        let program = optimized(",[>+>.<<-,]>>");
+        assert_eq!(program.ops(), &[
+            Op::In,
+            Op::JumpIfZero(7),
+            Op::AddAt(1, 1),
+            Op::OutAt(2),
+            Op::Add(-1),
+            Op::In,
+            Op::JumpIfNonZero(2),
+            Op::Move(2),
+        ]);
+    }
+
+    #[test]
+    fn test_address_offsets_tape_extent() {
+        let program = optimized("<<>>>>>+<<");
+        assert_eq!(program.ops(), &[Op::AddAt(-2, 0), Op::AddAt(3, 1), Op::Move(1)]);
+        let program = optimized("+>");
+        assert_eq!(program.ops(), &[Op::Add(1), Op::Move(1)]);
+    }
+}
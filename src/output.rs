@@ -0,0 +1,120 @@
+//! Lazy iteration over the output of a program, see `OutputBytes`.
+
+use std::iter::FusedIterator;
+
+use crate::error::Error;
+use crate::interpreter::{ExecutionContext, ExecutionStatus};
+use crate::ioctx::{InMemoryIoCtx, IoCtx};
+
+
+/// Iterator over the bytes of output of a program, executing it only as far as needed to produce
+/// the next byte. Obtained from `bfi::execute_iter` or `OutputBytes::new`.
+///
+/// Once the program stops the iterator ends, after yielding the `Error` it stopped with, if any.
+/// Dropping the iterator early leaves the rest of the program unexecuted, so that e.g. the first
+/// few bytes of a program that never terminates can be taken without setting a step limit.
+pub struct OutputBytes {
+    ectx: Option<ExecutionContext<InMemoryIoCtx>>,
+    error: Option<Error>,
+    done: bool,
+}
+
+
+impl OutputBytes {
+    /// Iterate over the output of the program in the context, from wherever it stands.
+    pub fn new(ectx: ExecutionContext<InMemoryIoCtx>) -> Self {
+        OutputBytes { ectx: Some(ectx), error: None, done: false }
+    }
+
+    /// Iterator over an execution that could not be started, yielding only the error.
+    pub(crate) fn failed(e: Error) -> Self {
+        OutputBytes { ectx: None, error: Some(e), done: true }
+    }
+
+    /// The context the program is executing in, e.g. to look at the tape between bytes, or
+    /// `None` if the execution could not be started.
+    pub fn context(&self) -> Option<&ExecutionContext<InMemoryIoCtx>> {
+        self.ectx.as_ref()
+    }
+
+    /// Take back the context the program is executing in, see `context`.
+    pub fn into_context(self) -> Option<ExecutionContext<InMemoryIoCtx>> {
+        self.ectx
+    }
+}
+
+
+impl Iterator for OutputBytes {
+    type Item = Result<u8, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let ectx = match self.ectx.as_mut() {
+            Some(ectx) => ectx,
+            None => return self.error.take().map(Err),
+        };
+        loop {
+            // output written before the program stopped comes out ahead of the error
+            let mut buf = [0u8; 1];
+            if let Ok(1) = ectx.get_mut().read_output(&mut buf) {
+                return Some(Ok(buf[0]))
+            };
+            if self.done {
+                return None
+            };
+            match ectx.step() {
+                ExecutionStatus::Terminated => self.done = true,
+                ExecutionStatus::Stopped(e) => {
+                    self.done = true;
+                    return Some(Err(e))
+                },
+                _ => {},
+            };
+        }
+    }
+}
+
+
+impl FusedIterator for OutputBytes {}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::Config;
+
+    fn output_bytes(program: &str, input: &[u8], config: Config) -> OutputBytes {
+        let mut ictx = InMemoryIoCtx::default();
+        ictx.write_input(input).unwrap();
+        OutputBytes::new(ExecutionContext::with_config(ictx, program, config))
+    }
+
+    #[test]
+    fn test_take_from_infinite_program() {
+        let mut output = output_bytes("+[.+]", b"", Config::default());
+        let bytes: Vec<u8> = output.by_ref().take(4).map(Result::unwrap).collect();
+        assert_eq!(bytes, vec![1, 2, 3, 4]);
+        // nothing past the fourth `.` has been executed
+        assert_eq!(output.context().unwrap().tape().to_vec(), vec![4]);
+    }
+
+    #[test]
+    fn test_terminates() {
+        let output: Result<Vec<u8>, Error> = output_bytes(",[.,]", b"abc", Config {
+            eof: crate::config::EofPolicy::Zero,
+            ..Config::default()
+        }).collect();
+        assert_eq!(output.unwrap(), b"abc");
+    }
+
+    #[test]
+    fn test_error() {
+        let config = Config { max_steps: Some(5), ..Config::default() };
+        let mut output = output_bytes("+.[]", b"", config);
+        assert_eq!(output.next(), Some(Ok(1)));
+        assert_eq!(output.next(), Some(Err(Error::StepLimit { steps: 5 })));
+        assert_eq!(output.next(), None);
+        let mut output = output_bytes("+.]", b"", Config::default());
+        assert!(matches!(output.next(), Some(Err(Error::UnmatchedClose(_)))));
+        assert_eq!(output.next(), None);
+    }
+}
@@ -6,11 +6,9 @@ use std::iter::Iterator;
 use rustyline::Editor;
 use rustyline::error::ReadlineError;
 
-use crate::token::Token;
-
 
 pub enum ReplResult<T> {
-    Command(Token),
+    Line(String),
     // Continue,
     Quit,
     Error(T),
@@ -19,7 +17,6 @@ pub enum ReplResult<T> {
 
 pub struct ReplInstance {
     editor: Editor<()>,
-    queue: Vec<Token>,
 }
 
 
@@ -36,7 +33,6 @@ Commands:
         );
         Self {
             editor: Editor::<()>::new(),
-            queue: Vec::new(),
         }
     }
 }
@@ -45,23 +41,19 @@ impl Iterator for ReplInstance {
     type Item = ReplResult<String>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.queue.is_empty() {
-            let input_line = self.editor.readline("bfi $ ");
-            match input_line {
-                // TODO: merge these two arms?
-                Err(ReadlineError::Eof) | Err(ReadlineError::Interrupted) => Some(ReplResult::Quit),
-                Ok(line) if line == "q" => Some(ReplResult::Quit),
-                // exits cleanly out of the REPL by ending iteration
-                Ok(line) if line == "c" => None,
-                Ok(line) => {
-                    self.editor.add_history_entry(line.as_str());
-                    self.queue.extend(Token::parse_str(line.as_str()).iter());
-                    self.next()
-                },
-                Err(e) => Some(ReplResult::Error(format!("{}", e))),
-            }
-        } else {
-            Some(ReplResult::Command(self.queue.remove(0)))
+        let input_line = self.editor.readline("bfi $ ");
+        match input_line {
+            // TODO: merge these two arms?
+            Err(ReadlineError::Eof) | Err(ReadlineError::Interrupted) => Some(ReplResult::Quit),
+            Ok(line) if line == "q" => Some(ReplResult::Quit),
+            // exits cleanly out of the REPL by ending iteration
+            Ok(line) if line == "c" => None,
+            Ok(line) => {
+                self.editor.add_history_entry(line.as_str());
+                // each line is run as its own program so that loops can be entered at the prompt
+                Some(ReplResult::Line(line))
+            },
+            Err(e) => Some(ReplResult::Error(format!("{}", e))),
         }
     }
 }
@@ -0,0 +1,462 @@
+//! Binary format of saved interpreter state, see `ExecutionContext::save`.
+//!
+//! A save starts with a magic number and a format version. Integers are little-endian and all
+//! variable-length values are prefixed by their length as a `u64`. The layout of the state itself
+//! is up to the interpreter, which reads it back in the order it was written.
+
+use std::convert::TryFrom;
+use std::io;
+use std::time::Duration;
+
+use crate::check::{Diagnostic, DiagnosticKind};
+use crate::config::{
+    CellWidth, Config, EdgePolicy, EofPolicy, LeftEdgePolicy, MemoryLimit, OverflowPolicy,
+};
+use crate::error::{Error, Position};
+use crate::interpreter::ExecutionStatus;
+use crate::token::Span;
+
+
+const MAGIC: &[u8] = b"bfi\0";
+const VERSION: u8 = 3;
+
+/// Kinds of I/O error that survive a save, any other kind is restored as `Other`.
+const IO_ERROR_KINDS: &[io::ErrorKind] = &[
+    io::ErrorKind::Other,
+    io::ErrorKind::NotFound,
+    io::ErrorKind::PermissionDenied,
+    io::ErrorKind::BrokenPipe,
+    io::ErrorKind::Interrupted,
+    io::ErrorKind::InvalidData,
+    io::ErrorKind::UnexpectedEof,
+    io::ErrorKind::WouldBlock,
+    io::ErrorKind::WriteZero,
+];
+
+
+type Result<T> = std::result::Result<T, Error>;
+
+
+/// Writes values to a new save.
+pub(crate) struct Writer {
+    buf: Vec<u8>,
+}
+
+
+impl Writer {
+    pub fn new() -> Self {
+        let mut buf = MAGIC.to_vec();
+        buf.push(VERSION);
+        Writer { buf }
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn i64(&mut self, value: i64) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn usize(&mut self, value: usize) {
+        self.u64(value as u64);
+    }
+
+    pub fn isize(&mut self, value: isize) {
+        self.i64(value as i64);
+    }
+
+    pub fn bytes(&mut self, value: &[u8]) {
+        self.usize(value.len());
+        self.buf.extend_from_slice(value);
+    }
+
+    pub fn option_u64(&mut self, value: Option<u64>) {
+        match value {
+            Some(v) => { self.u8(1); self.u64(v) },
+            None => self.u8(0),
+        };
+    }
+
+    pub fn option_duration(&mut self, value: Option<Duration>) {
+        match value {
+            Some(d) => { self.u8(1); self.u64(d.as_secs()); self.u64(u64::from(d.subsec_nanos())) },
+            None => self.u8(0),
+        };
+    }
+
+    pub fn config(&mut self, config: &Config) {
+        self.u8(config.cell_width.bits() as u8);
+        self.u8(match config.overflow {
+            OverflowPolicy::Wrap => 0,
+            OverflowPolicy::Saturate => 1,
+            OverflowPolicy::Error => 2,
+        });
+        self.option_u64(config.tape_size.map(|n| n as u64));
+        self.u8(match config.tape_edge {
+            EdgePolicy::Error => 0,
+            EdgePolicy::Wrap => 1,
+            EdgePolicy::Clamp => 2,
+        });
+        self.u8(match config.left_edge {
+            LeftEdgePolicy::Grow => 0,
+            LeftEdgePolicy::Error => 1,
+        });
+        self.u8(match config.eof {
+            EofPolicy::Unchanged => 0,
+            EofPolicy::Zero => 1,
+            EofPolicy::AllOnes => 2,
+            EofPolicy::Error => 3,
+        });
+        self.option_u64(config.max_steps);
+        self.option_duration(config.timeout);
+        match config.memory_limit {
+            None => self.u8(0),
+            Some(MemoryLimit::Cells(n)) => { self.u8(1); self.usize(n) },
+            Some(MemoryLimit::Bytes(n)) => { self.u8(2); self.usize(n) },
+        };
+    }
+
+    pub fn span(&mut self, span: Span) {
+        self.usize(span.offset);
+        self.usize(span.line);
+        self.usize(span.column);
+    }
+
+    pub fn position(&mut self, position: Position) {
+        self.usize(position.op);
+        match position.span {
+            Some(span) => { self.u8(1); self.span(span) },
+            None => self.u8(0),
+        };
+    }
+
+    pub fn diagnostic(&mut self, diagnostic: &Diagnostic) {
+        self.span(diagnostic.span);
+        self.bytes(diagnostic.snippet.as_bytes());
+    }
+
+    pub fn error(&mut self, error: &Error) {
+        match error {
+            Error::UnmatchedOpen(d) => { self.u8(0); self.diagnostic(d) },
+            Error::UnmatchedClose(d) => { self.u8(1); self.diagnostic(d) },
+            Error::InvalidEncoding => self.u8(2),
+            Error::InvalidConfig(reason) => { self.u8(3); self.bytes(reason.as_bytes()) },
+            Error::InvalidSave(reason) => { self.u8(4); self.bytes(reason.as_bytes()) },
+            Error::Io { kind, message, position } => {
+                self.u8(5);
+                self.u8(IO_ERROR_KINDS.iter().position(|k| k == kind).unwrap_or(0) as u8);
+                self.bytes(message.as_bytes());
+                match position {
+                    Some(position) => { self.u8(1); self.position(*position) },
+                    None => self.u8(0),
+                };
+            },
+            Error::Repl(message) => { self.u8(6); self.bytes(message.as_bytes()) },
+            Error::Overflow { cell, position } => {
+                self.u8(7);
+                self.isize(*cell);
+                self.position(*position);
+            },
+            Error::OffTape { cell, position } => {
+                self.u8(8);
+                self.isize(*cell);
+                self.position(*position);
+            },
+            Error::InputExhausted { position } => { self.u8(9); self.position(*position) },
+            Error::MemoryLimit { cell, position } => {
+                self.u8(10);
+                self.isize(*cell);
+                self.position(*position);
+            },
+            Error::StepLimit { steps } => { self.u8(11); self.u64(*steps) },
+            Error::Timeout { steps } => { self.u8(12); self.u64(*steps) },
+            Error::Cancelled => self.u8(13),
+            Error::Unfinished => self.u8(14),
+        };
+    }
+
+    pub fn status(&mut self, status: &ExecutionStatus) {
+        match status {
+            ExecutionStatus::NotStarted => self.u8(0),
+            ExecutionStatus::InProgress => self.u8(1),
+            ExecutionStatus::Terminated => self.u8(2),
+            ExecutionStatus::Stopped(e) => { self.u8(3); self.error(e) },
+        };
+    }
+}
+
+
+/// Reads values back from a save, in the order they were written.
+pub(crate) struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+
+impl<'a> Reader<'a> {
+    /// Start reading a save, checking that it is one and that its format is supported.
+    pub fn new(buf: &'a [u8]) -> Result<Self> {
+        let mut reader = Reader { buf };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(invalid_save("not a saved execution"));
+        };
+        match reader.u8()? {
+            VERSION => Ok(reader),
+            v => Err(invalid_save(format!("unsupported save format version {}", v))),
+        }
+    }
+
+    /// Check that the whole save has been read.
+    pub fn finish(self) -> Result<()> {
+        match self.buf.len() {
+            0 => Ok(()),
+            n => Err(invalid_save(format!("{} unexpected bytes at the end of the save", n))),
+        }
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if n > self.buf.len() {
+            return Err(invalid_save("save is truncated"));
+        };
+        let (taken, rest) = self.buf.split_at(n);
+        self.buf = rest;
+        Ok(taken)
+    }
+
+    pub fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u64(&mut self) -> Result<u64> {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    pub fn i64(&mut self) -> Result<i64> {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(i64::from_le_bytes(bytes))
+    }
+
+    pub fn usize(&mut self) -> Result<usize> {
+        let value = self.u64()?;
+        usize::try_from(value).map_err(|_| out_of_range(value))
+    }
+
+    pub fn isize(&mut self) -> Result<isize> {
+        let value = self.i64()?;
+        isize::try_from(value).map_err(|_| out_of_range(value))
+    }
+
+    pub fn bytes(&mut self) -> Result<&'a [u8]> {
+        let n = self.usize()?;
+        self.take(n)
+    }
+
+    pub fn string(&mut self) -> Result<String> {
+        String::from_utf8(self.bytes()?.to_vec())
+            .map_err(|_| invalid_save("saved text is not UTF-8"))
+    }
+
+    pub fn option_u64(&mut self) -> Result<Option<u64>> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => Ok(Some(self.u64()?)),
+            tag => Err(invalid("option", tag)),
+        }
+    }
+
+    pub fn option_duration(&mut self) -> Result<Option<Duration>> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => {
+                let secs = self.u64()?;
+                let nanos = self.u64()?;
+                match u32::try_from(nanos) {
+                    Ok(nanos) if nanos < 1_000_000_000 => Ok(Some(Duration::new(secs, nanos))),
+                    _ => Err(out_of_range(nanos)),
+                }
+            },
+            tag => Err(invalid("option", tag)),
+        }
+    }
+
+    pub fn config(&mut self) -> Result<Config> {
+        let bits = self.u8()?;
+        Ok(Config {
+            cell_width: CellWidth::from_bits(u32::from(bits))
+                .ok_or_else(|| invalid("cell width", bits))?,
+            overflow: match self.u8()? {
+                0 => OverflowPolicy::Wrap,
+                1 => OverflowPolicy::Saturate,
+                2 => OverflowPolicy::Error,
+                tag => return Err(invalid("overflow policy", tag)),
+            },
+            tape_size: match self.option_u64()? {
+                Some(n) => Some(usize::try_from(n).map_err(|_| out_of_range(n))?),
+                None => None,
+            },
+            tape_edge: match self.u8()? {
+                0 => EdgePolicy::Error,
+                1 => EdgePolicy::Wrap,
+                2 => EdgePolicy::Clamp,
+                tag => return Err(invalid("edge policy", tag)),
+            },
+            left_edge: match self.u8()? {
+                0 => LeftEdgePolicy::Grow,
+                1 => LeftEdgePolicy::Error,
+                tag => return Err(invalid("left edge policy", tag)),
+            },
+            eof: match self.u8()? {
+                0 => EofPolicy::Unchanged,
+                1 => EofPolicy::Zero,
+                2 => EofPolicy::AllOnes,
+                3 => EofPolicy::Error,
+                tag => return Err(invalid("EOF policy", tag)),
+            },
+            max_steps: self.option_u64()?,
+            timeout: self.option_duration()?,
+            memory_limit: match self.u8()? {
+                0 => None,
+                1 => Some(MemoryLimit::Cells(self.usize()?)),
+                2 => Some(MemoryLimit::Bytes(self.usize()?)),
+                tag => return Err(invalid("memory limit", tag)),
+            },
+        })
+    }
+
+    pub fn span(&mut self) -> Result<Span> {
+        Ok(Span { offset: self.usize()?, line: self.usize()?, column: self.usize()? })
+    }
+
+    pub fn position(&mut self) -> Result<Position> {
+        let op = self.usize()?;
+        let span = match self.u8()? {
+            0 => None,
+            1 => Some(self.span()?),
+            tag => return Err(invalid("span", tag)),
+        };
+        Ok(Position { op, span })
+    }
+
+    pub fn diagnostic(&mut self, kind: DiagnosticKind) -> Result<Diagnostic> {
+        Ok(Diagnostic { kind, span: self.span()?, snippet: self.string()? })
+    }
+
+    pub fn error(&mut self) -> Result<Error> {
+        Ok(match self.u8()? {
+            0 => Error::UnmatchedOpen(self.diagnostic(DiagnosticKind::UnmatchedOpen)?),
+            1 => Error::UnmatchedClose(self.diagnostic(DiagnosticKind::UnmatchedClose)?),
+            2 => Error::InvalidEncoding,
+            3 => Error::InvalidConfig(self.string()?),
+            4 => Error::InvalidSave(self.string()?),
+            5 => {
+                let tag = self.u8()?;
+                let kind = *IO_ERROR_KINDS.get(usize::from(tag))
+                    .ok_or_else(|| invalid("I/O error kind", tag))?;
+                let message = self.string()?;
+                let position = match self.u8()? {
+                    0 => None,
+                    1 => Some(self.position()?),
+                    tag => return Err(invalid("position", tag)),
+                };
+                Error::Io { kind, message, position }
+            },
+            6 => Error::Repl(self.string()?),
+            7 => Error::Overflow { cell: self.isize()?, position: self.position()? },
+            8 => Error::OffTape { cell: self.isize()?, position: self.position()? },
+            9 => Error::InputExhausted { position: self.position()? },
+            10 => Error::MemoryLimit { cell: self.isize()?, position: self.position()? },
+            11 => Error::StepLimit { steps: self.u64()? },
+            12 => Error::Timeout { steps: self.u64()? },
+            13 => Error::Cancelled,
+            14 => Error::Unfinished,
+            tag => return Err(invalid("error", tag)),
+        })
+    }
+
+    pub fn status(&mut self) -> Result<ExecutionStatus> {
+        Ok(match self.u8()? {
+            0 => ExecutionStatus::NotStarted,
+            1 => ExecutionStatus::InProgress,
+            2 => ExecutionStatus::Terminated,
+            3 => ExecutionStatus::Stopped(self.error()?),
+            tag => return Err(invalid("status", tag)),
+        })
+    }
+}
+
+
+pub(crate) fn invalid_save<S: Into<String>>(reason: S) -> Error {
+    Error::InvalidSave(reason.into())
+}
+
+
+fn invalid(what: &str, tag: u8) -> Error {
+    invalid_save(format!("invalid {} {} in save", what, tag))
+}
+
+
+fn out_of_range<T: std::fmt::Display>(value: T) -> Error {
+    invalid_save(format!("saved value {} is out of range", value))
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let config = Config {
+            cell_width: CellWidth::U32,
+            tape_size: Some(30_000),
+            eof: EofPolicy::AllOnes,
+            // longer than fits in a `u64` of nanoseconds
+            timeout: Some(Duration::new(20_000_000_000, 5)),
+            memory_limit: Some(MemoryLimit::Bytes(1 << 20)),
+            ..Config::default()
+        };
+        let span = Some(Span { offset: 7, line: 2, column: 3 });
+        let status = ExecutionStatus::Stopped(Error::Io {
+            kind: io::ErrorKind::BrokenPipe,
+            message: "oops".to_string(),
+            position: Some(Position { op: 4, span }),
+        });
+        let mut writer = Writer::new();
+        writer.config(&config);
+        writer.status(&status);
+        writer.isize(-3);
+        writer.bytes(b"input");
+        let saved = writer.finish();
+        let mut reader = Reader::new(&saved).unwrap();
+        assert_eq!(reader.config(), Ok(config));
+        assert_eq!(reader.status(), Ok(status));
+        assert_eq!(reader.isize(), Ok(-3));
+        assert_eq!(reader.bytes(), Ok(&b"input"[..]));
+        assert_eq!(reader.finish(), Ok(()));
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(Reader::new(b"bf").is_err());
+        assert!(Reader::new(b"bfi\0\x01").is_err());
+        let mut reader = Reader::new(b"bfi\0\x03\x05\x00").unwrap();
+        assert!(reader.u64().is_err());
+        let mut reader = Reader::new(b"bfi\0\x03\x07").unwrap();
+        assert!(reader.config().is_err());
+        let mut reader = Reader::new(b"bfi\0\x03\x03\x0f").unwrap();
+        assert!(matches!(reader.status(), Err(Error::InvalidSave(_))));
+        let reader = Reader::new(b"bfi\0\x03\x00").unwrap();
+        assert!(reader.finish().is_err());
+    }
+}
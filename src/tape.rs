@@ -0,0 +1,176 @@
+//! The roll of tape that programs operate on.
+
+use std::collections::VecDeque;
+use std::fmt::{self, Debug};
+
+
+/// A tape of cells extending as far as needed in both directions.
+///
+/// Cells are stored as `i64`s, wide enough for any `config::CellWidth`. Keeping the values in
+/// range for the width in use is up to the interpreter.
+///
+/// Cells are addressed by their logical index relative to the starting cell (index 0), so
+/// indices to the left of the start are negative. The tape grows in amortized constant time at
+/// either end and cells are zero until written.
+#[derive(Clone, PartialEq)]
+pub struct Tape {
+    cells: VecDeque<i64>,
+
+    /// Position in `cells` of the starting cell.
+    origin: usize,
+}
+
+
+impl Default for Tape {
+    fn default() -> Self {
+        Tape {
+            cells: VecDeque::from(vec![0]),
+            origin: 0,
+        }
+    }
+}
+
+
+impl Debug for Tape {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} (cells {} to {})", self.cells, self.start(), self.end() - 1)
+    }
+}
+
+
+impl Tape {
+    /// Rebuild a tape from the cells between `start` and the `end` of a previous tape, which must
+    /// include the starting cell.
+    pub fn from_cells(start: isize, cells: Vec<i64>) -> Option<Self> {
+        if start > 0 || start + (cells.len() as isize) <= 0 {
+            return None;
+        };
+        Some(Tape {
+            cells: VecDeque::from(cells),
+            origin: -start as usize,
+        })
+    }
+
+    /// Logical index of the leftmost cell reached so far.
+    pub fn start(&self) -> isize {
+        -(self.origin as isize)
+    }
+
+    /// Logical index one past the rightmost cell reached so far.
+    pub fn end(&self) -> isize {
+        self.start() + self.cells.len() as isize
+    }
+
+    /// Number of cells reached so far.
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    /// Always `false`, the starting cell is always present.
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    /// Value of the cell at the index. Cells that have not been reached are zero.
+    pub fn get(&self, index: isize) -> i64 {
+        match self.position(index) {
+            Some(i) => self.cells[i],
+            None => 0,
+        }
+    }
+
+    /// Mutable reference to the cell at the index, growing the tape to reach it if necessary.
+    pub fn get_mut(&mut self, index: isize) -> &mut i64 {
+        self.grow_to(index);
+        let i = (index - self.start()) as usize;
+        &mut self.cells[i]
+    }
+
+    /// Grow the tape such that it includes the cell at the index.
+    pub fn grow_to(&mut self, index: isize) {
+        if index < self.start() {
+            let missing = (self.start() - index) as usize;
+            self.cells.reserve(missing);
+            for _ in 0..missing {
+                self.cells.push_front(0);
+            }
+            self.origin += missing;
+        } else if index >= self.end() {
+            let length = (index - self.start()) as usize + 1;
+            self.cells.resize(length, 0);
+        };
+    }
+
+    /// Number of cells the tape would have after growing to include the cell at the index.
+    pub fn len_with(&self, index: isize) -> usize {
+        (self.end().max(index + 1) - self.start().min(index)) as usize
+    }
+
+    /// Copy of all cells reached so far, from `start` to `end`.
+    pub fn to_vec(&self) -> Vec<i64> {
+        self.cells.iter().copied().collect()
+    }
+
+    fn position(&self, index: isize) -> Option<usize> {
+        if index >= self.start() && index < self.end() {
+            Some((index - self.start()) as usize)
+        } else {
+            None
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_grow_right() {
+        let mut tape = Tape::default();
+        *tape.get_mut(3) = 7;
+        assert_eq!(vec![0, 0, 0, 7], tape.to_vec());
+        assert_eq!((0, 4), (tape.start(), tape.end()));
+        assert_eq!(7, tape.get(3));
+        assert_eq!(0, tape.get(4));
+    }
+
+    #[test]
+    fn test_grow_left() {
+        let mut tape = Tape::default();
+        *tape.get_mut(0) = 1;
+        *tape.get_mut(-2) = 2;
+        assert_eq!(vec![2, 0, 1], tape.to_vec());
+        assert_eq!((-2, 1), (tape.start(), tape.end()));
+        assert_eq!((2, 1), (tape.get(-2), tape.get(0)));
+        assert_eq!(0, tape.get(-3));
+        assert_eq!((3, 4, 5), (tape.len_with(0), tape.len_with(1), tape.len_with(-4)));
+    }
+
+    #[test]
+    fn test_walk_left() {
+        let mut tape = Tape::default();
+        for i in 0..100_000 {
+            *tape.get_mut(-i) = 1;
+        }
+        assert_eq!(100_000, tape.len());
+        assert_eq!(-99_999, tape.start());
+    }
+
+    #[test]
+    fn test_from_cells() {
+        let mut tape = Tape::default();
+        *tape.get_mut(-2) = 3;
+        *tape.get_mut(1) = 4;
+        assert_eq!(Tape::from_cells(tape.start(), tape.to_vec()), Some(tape));
+        assert_eq!(Tape::from_cells(1, vec![0]), None);
+        assert_eq!(Tape::from_cells(-1, vec![0]), None);
+    }
+
+    #[test]
+    fn test_debug_fmt() {
+        let mut tape = Tape::default();
+        *tape.get_mut(-1) = 5;
+        assert_eq!("[5, 0] (cells -1 to 0)", format!("{:?}", tape));
+    }
+}
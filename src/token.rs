@@ -59,6 +59,40 @@ impl Token {
     pub fn parse_str(s: &str) -> Vec<Self> {
         s.chars().filter_map(|c| Token::decode(c).ok()).collect()
     }
+
+    /// Parse a `&str` like `parse_str`, keeping the location in the source of each `Token`.
+    pub fn parse_spanned(s: &str) -> Vec<(Self, Span)> {
+        let mut tokens: Vec<(Token, Span)> = Vec::new();
+        let mut span = Span { offset: 0, line: 1, column: 1 };
+        for (offset, c) in s.char_indices() {
+            span.offset = offset;
+            if let Ok(t) = Token::decode(c) {
+                tokens.push((t, span));
+            };
+            match c {
+                '\n' => {
+                    span.line += 1;
+                    span.column = 1;
+                },
+                _ => span.column += 1,
+            };
+        }
+        tokens
+    }
+}
+
+
+/// Location of a single command in the program source.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Span {
+    /// Byte offset of the command from the start of the source.
+    pub offset: usize,
+
+    /// Line number, starting from 1.
+    pub line: usize,
+
+    /// Column number in characters, starting from 1.
+    pub column: usize,
 }
 
 
@@ -69,6 +103,13 @@ impl fmt::Display for Token {
 }
 
 
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -89,9 +130,9 @@ mod test {
 
     #[test]
     fn decoding() {
-        for (c, &t) in SYMBOLS.chars().zip(TOKENS.into_iter()) {
+        for (c, &t) in SYMBOLS.chars().zip(TOKENS.iter()) {
             let decoded: Result<Token, char> = Token::decode(c);
-            assert_eq!(decoded.is_ok(), true);
+            assert!(decoded.is_ok());
             assert_eq!(decoded.unwrap(), t);
         }
     }
@@ -100,14 +141,25 @@ mod test {
     fn decoding_comments() {
         let program: &str = "0>something <+\t-.else,[]#\nentirely 💁%";
         let program_decoded: Vec<Token> = Token::parse_str(program);
-        for (&c, &t) in program_decoded.iter().zip(TOKENS.into_iter()) {
+        for (&c, &t) in program_decoded.iter().zip(TOKENS.iter()) {
             assert_eq!(c, t);
         }
     }
 
+    #[test]
+    fn decoding_spans() {
+        let program: &str = "a+\n 💁[\n]";
+        let program_decoded: Vec<(Token, Span)> = Token::parse_spanned(program);
+        assert_eq!(program_decoded, vec![
+            (Token::ValInc, Span { offset: 1, line: 1, column: 2 }),
+            (Token::LoopBeg, Span { offset: 8, line: 2, column: 3 }),
+            (Token::LoopEnd, Span { offset: 10, line: 3, column: 1 }),
+        ]);
+    }
+
     #[test]
     fn encoding() {
-        for (c, &t) in SYMBOLS.chars().zip(TOKENS.into_iter()) {
+        for (c, &t) in SYMBOLS.chars().zip(TOKENS.iter()) {
             assert_eq!(Token::encode(t), c);
         }
     }
@@ -169,7 +169,7 @@ fn sierpinski(n: u64) -> Vec<String> {
         vec!["*".to_string()]
     } else {
         let prev = sierpinski(n - 1);
-        let prev_width = prev.iter().rev().nth(0).unwrap().len();
+        let prev_width = prev.last().unwrap().len();
         let next_width = prev_width * 2 + 1;
         let mut next: Vec<String> = Vec::new();
         for (i, cur) in prev.iter().enumerate() {
@@ -203,3 +203,190 @@ fn test_missing_file() {
         .execute();
 }
 
+
+#[test]
+fn test_check_valid() {
+    TestCase::new()
+        .with_arg("check")
+        .with_arg(",[.[-],]")
+        .expect_stdout("")
+        .expect_stderr("")
+        .execute();
+}
+
+#[test]
+fn test_check_unmatched_brackets() {
+    TestCase::new()
+        .with_arg("check")
+        .with_arg("+[\n-]]")
+        .expect_stdout("")
+        .expect_stderr("bfi: unmatched ']' at line 2, column 3\n  -]]\n    ^\n")
+        .expect_retcode(1)
+        .execute();
+}
+
+#[test]
+fn test_check_requires_program() {
+    TestCase::new()
+        .with_arg("check")
+        .expect_stdout("")
+        .expect_retcode(1)
+        .execute();
+}
+
+#[test]
+fn test_cell_size() {
+    // 256 wraps to 0 with 8-bit cells, skipping the loop that prints 'A'
+    let program = format!("++++++++[>{}<-]>[>{}.<[-]]", "+".repeat(32), "+".repeat(65));
+    TestCase::new()
+        .with_arg(&program)
+        .expect_stdout("")
+        .execute();
+    TestCase::new()
+        .with_arg("--cell-size")
+        .with_arg("16")
+        .with_arg(&program)
+        .expect_stdout("A")
+        .execute();
+    TestCase::new()
+        .with_arg("--cell-size")
+        .with_arg("12")
+        .with_arg(&program)
+        .expect_retcode(1)
+        .execute();
+}
+
+#[test]
+fn test_overflow() {
+    TestCase::new()
+        .with_arg("--overflow")
+        .with_arg("saturate")
+        .with_arg("saturate --.+.")
+        .expect_stdout("\u{0}\u{1}")
+        .execute();
+    TestCase::new()
+        .with_arg("--overflow")
+        .with_arg("error")
+        .with_arg("+.\n -.-.")
+        .expect_stdout("\u{1}\u{0}")
+        .expect_stderr("bfi: exited with error: \
+            cell 0 overflowed at program position 4 (line 2, column 4)\n")
+        .expect_retcode(1)
+        .execute();
+}
+
+#[test]
+fn test_bounded_tape() {
+    TestCase::new()
+        .with_arg("--tape-size")
+        .with_arg("3")
+        .with_arg(">>>+.")
+        .expect_stderr("bfi: exited with error: data pointer moved to cell 3, past the end of the \
+            tape at program position 0 (line 1, column 1)\n")
+        .expect_retcode(1)
+        .execute();
+    TestCase::new()
+        .with_arg("--tape-size")
+        .with_arg("3")
+        .with_arg("--tape-edge")
+        .with_arg("wrap")
+        .with_arg("+<<<.")
+        .expect_stdout("\u{1}")
+        .execute();
+    TestCase::new()
+        .with_arg("--tape-size")
+        .with_arg("18446744073709551615")
+        .with_arg("--tape-edge")
+        .with_arg("clamp")
+        .with_arg("<+")
+        .expect_stderr("bfi: exited with error: invalid options: a bounded tape can have at most \
+            9223372036854775807 cells\n")
+        .expect_retcode(1)
+        .execute();
+    TestCase::new()
+        .with_arg("--left-edge")
+        .with_arg("error")
+        .with_arg("+.<")
+        .expect_stdout("\u{1}")
+        .expect_stderr("bfi: exited with error: data pointer moved to cell -1, past the start of \
+            the tape at program position 2 (line 1, column 3)\n")
+        .expect_retcode(1)
+        .execute();
+}
+
+#[test]
+fn test_eof() {
+    TestCase::new()
+        .with_arg("--eof")
+        .with_arg("all-ones")
+        .with_arg(",+.")
+        .expect_stdout("\u{0}")
+        .execute();
+    TestCase::new()
+        .with_arg("--eof")
+        .with_arg("error")
+        .with_arg(",")
+        .expect_stderr("bfi: exited with error: \
+            input exhausted at program position 0 (line 1, column 1)\n")
+        .expect_retcode(1)
+        .execute();
+}
+
+#[test]
+fn test_execution_budget() {
+    TestCase::new()
+        .with_arg("--max-steps")
+        .with_arg("1000")
+        .with_arg("+.[]")
+        .expect_stdout("\u{1}")
+        .expect_stderr("bfi: exited with error: step limit reached after 1000 steps\n")
+        .expect_retcode(1)
+        .execute();
+    TestCase::new()
+        .with_arg("--timeout")
+        .with_arg("0.01")
+        .with_arg("+[]")
+        .expect_retcode(1)
+        .execute();
+    TestCase::new()
+        .with_arg("--timeout")
+        .with_arg("1e30")
+        .with_arg("+")
+        .expect_retcode(1)
+        .execute();
+}
+
+#[test]
+fn test_memory_limit() {
+    TestCase::new()
+        .with_arg("--max-cells")
+        .with_arg("100")
+        .with_arg("+[>+]")
+        .expect_stderr("bfi: exited with error: memory limit exceeded reaching cell 100 at \
+            program position 2 (line 1, column 3)\n")
+        .expect_retcode(1)
+        .execute();
+    TestCase::new()
+        .with_arg("--max-bytes")
+        .with_arg("80")
+        .with_arg("+[<+]")
+        .expect_stderr("bfi: exited with error: memory limit exceeded reaching cell -10 at \
+            program position 2 (line 1, column 3)\n")
+        .expect_retcode(1)
+        .execute();
+}
+
+#[test]
+fn test_broken_pipe() {
+    let mut child_proc = Command::new(&*TestCase::new().executable)
+        .arg("+[.]")
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .expect("failed to execute");
+    // hang up on the program, which would otherwise write forever
+    drop(child_proc.stdout.take());
+    let child_output = child_proc.wait_with_output().expect("failed to wait on bfi");
+    assert_eq!(Some(0), child_output.status.code());
+    assert_eq!("", std::str::from_utf8(&child_output.stderr).unwrap());
+}